@@ -1,93 +1,420 @@
-use crate::math::{Mesh, Triangle, Vec4};
+use crate::assets::image::ImageError;
+use crate::assets::mtl::{load_materials, TextureCache};
+use crate::lighting::Material;
+use crate::math::{triangulate_polygon, Mesh, Submesh, Vec2, Vec4, Vertex};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::io;
 use std::io::{BufRead, BufReader, Lines};
+use std::path::Path;
+use std::str::FromStr;
 
-pub fn load_model(path: String) -> Mesh {
-    // println!("Working path: {}", current_dir().unwrap().display());
-    // for path_element in read_dir(".").unwrap() {
-    //     println!("File/folder: {}", path_element.unwrap().path().display())
-    // }
-    //
+mod deflate;
+pub mod image;
+mod inflate;
+mod mtl;
+mod png;
+
+/// Faces meeting at a sharper angle than this keep a hard edge when normals are generated.
+pub const DEFAULT_CREASE_ANGLE: f32 = 60.0;
+
+#[derive(Debug)]
+pub enum ModelError {
+    /// `line` is 0 when the file could not be opened at all.
+    Io { path: String, line: usize, source: io::Error },
+    BadNumber { path: String, line: usize, token: String },
+    IndexOutOfRange { path: String, line: usize, index: i64 },
+    UnsupportedStatement { path: String, line: usize, statement: String },
+    UnknownMaterial { path: String, line: usize, name: String },
+    Image { path: String, line: usize, source: ImageError },
+}
+
+/// Mesh loaded from a file together with the materials its submeshes refer to.
+pub struct Model {
+    pub mesh: Mesh,
+    pub materials: Vec<Material>,
+}
+
+impl Display for ModelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModelError::Io { path, line: 0, source } =>
+                write!(f, "{}: {}", path, source),
+            ModelError::Io { path, line, source } =>
+                write!(f, "{}:{}: {}", path, line, source),
+            ModelError::BadNumber { path, line, token } if token.is_empty() =>
+                write!(f, "{}:{}: expected a number, found end of line", path, line),
+            ModelError::BadNumber { path, line, token } =>
+                write!(f, "{}:{}: expected a number, found '{}'", path, line, token),
+            ModelError::IndexOutOfRange { path, line, index } =>
+                write!(f, "{}:{}: index {} is out of range", path, line, index),
+            ModelError::UnsupportedStatement { path, line, statement } =>
+                write!(f, "{}:{}: unsupported statement '{}'", path, line, statement),
+            ModelError::UnknownMaterial { path, line, name } =>
+                write!(f, "{}:{}: material '{}' is not defined", path, line, name),
+            ModelError::Image { path, line, source } =>
+                write!(f, "{}:{}: {}", path, line, source),
+        }
+    }
+}
+
+impl std::error::Error for ModelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ModelError::Io { source, .. } => Some(source),
+            ModelError::Image { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct ObjData {
+    positions: Vec<Vec4>,
+    uvs: Vec<Vec2<f32>>,
+    normals: Vec<Vec4>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// Position of the line being parsed, used to attach the location to every `ModelError`.
+struct LineLocation<'a> {
+    path: &'a str,
+    line: usize,
+}
+
+impl LineLocation<'_> {
+    fn parse<T: FromStr>(&self, token: Option<&str>) -> Result<T, ModelError> {
+        let token = token.unwrap_or("");
+        return token.parse().map_err(|_| ModelError::BadNumber {
+            path: self.path.to_string(),
+            line: self.line,
+            token: token.to_string(),
+        });
+    }
+
+    /// Resolves a 1-based OBJ index, where negative values count back from the last `count` element.
+    fn index(&self, token: &str, count: usize) -> Result<usize, ModelError> {
+        let index: i64 = self.parse(Some(token))?;
+        let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(ModelError::IndexOutOfRange { path: self.path.to_string(), line: self.line, index });
+        }
+        return Ok(resolved as usize);
+    }
+
+    /// Parses a `v`, `v/vt`, `v//vn` or `v/vt/vn` face corner.
+    fn face_vertex(&self, token: &str, data: &ObjData) -> Result<FaceVertex, ModelError> {
+        let mut indices = token.split('/');
+        let position = self.index(indices.next().unwrap_or(""), data.positions.len())?;
+        let uv = match indices.next() {
+            Some("") | None => None,
+            Some(uv) => Some(self.index(uv, data.uvs.len())?),
+        };
+        let normal = match indices.next() {
+            Some("") | None => None,
+            Some(normal) => Some(self.index(normal, data.normals.len())?),
+        };
+        return Ok(FaceVertex { position, uv, normal });
+    }
+
+    fn unsupported(&self, statement: &str) -> ModelError {
+        ModelError::UnsupportedStatement {
+            path: self.path.to_string(),
+            line: self.line,
+            statement: statement.to_string(),
+        }
+    }
+}
+
+/// Loads an OBJ file with the materials of its MTL libraries. Faces are grouped into one submesh
+/// per material, and faces before the first `usemtl` use the default material.
+pub fn load_model(path: &str) -> Result<Model, ModelError> {
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+    let mut data = ObjData::default();
     let mut vertices = vec![];
-    let mut triangles = vec![];
-    if let Ok(lines) = read_lines(path) {
-        for line in lines.flatten() {
-            let line_tokens: Vec<&str> = line.split_whitespace().collect();
-            if line.starts_with("v ") {
-                vertices.push(Vec4::new3d(line_tokens[1].parse().unwrap(),
-                                          line_tokens[2].parse().unwrap(),
-                                          line_tokens[3].parse().unwrap()))
-            } else if line.starts_with("f ") {
-                let v1_ind: usize = line_tokens[1].split("/").collect::<Vec<&str>>()[0].parse::<usize>().unwrap() - 1;
-                let v2_ind: usize = line_tokens[2].split("/").collect::<Vec<&str>>()[0].parse::<usize>().unwrap() - 1;
-                let v3_ind: usize = line_tokens[3].split("/").collect::<Vec<&str>>()[0].parse::<usize>().unwrap() - 1;
-                triangles.push(Triangle::new(vertices[v1_ind].clone(), vertices[v2_ind].clone(), vertices[v3_ind].clone()));
-
-                if line_tokens.len() > 4 { // this is a quad
-                    let v4_ind: usize = line_tokens[4].split("/").collect::<Vec<&str>>()[0].parse::<usize>().unwrap() - 1;
-                    triangles.push(Triangle::new(vertices[v1_ind].clone(), vertices[v3_ind].clone(), vertices[v4_ind].clone()));
+    let mut vertex_indices = HashMap::new();
+    let mut library: HashMap<String, Material> = HashMap::new();
+    let mut textures = TextureCache::new();
+    let mut materials: Vec<Material> = vec![];
+    let mut material_ids: HashMap<String, usize> = HashMap::new();
+    // indices of the faces drawn with every entry of `materials`
+    let mut material_indices: Vec<Vec<u32>> = vec![];
+    let mut current_material = None;
+
+    let lines = read_lines(path).map_err(|source| ModelError::Io { path: path.to_string(), line: 0, source })?;
+    for (line_ind, line) in lines.enumerate() {
+        let location = LineLocation { path, line: line_ind + 1 };
+        let line = line.map_err(|source| ModelError::Io { path: path.to_string(), line: location.line, source })?;
+        let mut line_tokens = line.split_whitespace();
+        match line_tokens.next() {
+            Some("v") => {
+                data.positions.push(Vec4::new3d(location.parse(line_tokens.next())?,
+                                                location.parse(line_tokens.next())?,
+                                                location.parse(line_tokens.next())?));
+            }
+            Some("vt") => {
+                let u = location.parse(line_tokens.next())?;
+                let v = line_tokens.next().map_or(Ok(0.0), |v| location.parse(Some(v)))?;
+                data.uvs.push(Vec2::new(u, v));
+            }
+            Some("vn") => {
+                data.normals.push(Vec4::new(location.parse(line_tokens.next())?,
+                                            location.parse(line_tokens.next())?,
+                                            location.parse(line_tokens.next())?,
+                                            0.0));
+            }
+            Some("f") => {
+                let face = line_tokens.map(|token| location.face_vertex(token, &data))
+                    .collect::<Result<Vec<FaceVertex>, ModelError>>()?;
+                if face.len() < 3 {
+                    return Err(location.unsupported(line.trim()));
+                }
+                let material = *current_material.get_or_insert_with(|| {
+                    materials.push(Material::default());
+                    material_indices.push(vec![]);
+                    materials.len() - 1
+                });
+                push_face(&mut vertices, &mut material_indices[material], &mut vertex_indices, &face, &data);
+            }
+            Some("mtllib") => {
+                for library_path in line_tokens {
+                    let library_path = directory.join(library_path).to_string_lossy().into_owned();
+                    library.extend(load_materials(&library_path, &mut textures)?);
                 }
             }
+            Some("usemtl") => {
+                let name = line_tokens.collect::<Vec<&str>>().join(" ");
+                if let Some(&id) = material_ids.get(&name) {
+                    current_material = Some(id);
+                    continue;
+                }
+                let Some(material) = library.get(&name) else {
+                    return Err(ModelError::UnknownMaterial { path: path.to_string(), line: location.line, name });
+                };
+                materials.push(material.clone());
+                material_indices.push(vec![]);
+                material_ids.insert(name, materials.len() - 1);
+                current_material = Some(materials.len() - 1);
+            }
+            // grouping, display and render attributes, points and lines, and free-form curves and surfaces,
+            // none of which change the triangles we render
+            Some("o" | "g" | "s" | "mg" | "usemap" | "maplib" | "lod" | "bevel" | "c_interp" | "d_interp"
+                 | "shadow_obj" | "trace_obj" | "ctech" | "stech" | "p" | "l" | "vp" | "cstype" | "deg" | "bmat"
+                 | "step" | "curv" | "curv2" | "surf" | "parm" | "trim" | "hole" | "scrv" | "sp" | "end" | "con")
+            | None => {}
+            Some(comment) if comment.starts_with('#') => {}
+            Some(statement) => return Err(location.unsupported(statement)),
         }
-
-        return Mesh { triangles };
     }
 
-    return cube();
+    let mut mesh = Mesh { vertices, indices: vec![], submeshes: vec![] };
+    for (material, indices) in material_indices.into_iter().enumerate() {
+        if !indices.is_empty() {
+            mesh.submeshes.push(Submesh { indices: mesh.indices.len()..mesh.indices.len() + indices.len(), material });
+            mesh.indices.extend(indices);
+        }
+    }
+    mesh.generate_normals(DEFAULT_CREASE_ANGLE);
+    if materials.is_empty() {
+        materials.push(Material::default());
+    }
+    return Ok(Model { mesh, materials });
 }
 
-fn cube() -> Mesh {
-    Mesh {
-        triangles: vec![
-            // front
-            Triangle::new(Vec4::new3d(-0.5, -0.5, 1.5),
-                          Vec4::new3d(-0.5, 0.5, 1.5),
-                          Vec4::new3d(0.5, 0.5, 1.5)),
-            Triangle::new(Vec4::new3d(-0.5, -0.5, 1.5),
-                          Vec4::new3d(0.5, 0.5, 1.5),
-                          Vec4::new3d(0.5, -0.5, 1.5)),
-            //     // back
-            Triangle::new(Vec4::new3d(-0.5, -0.5, 2.5),
-                          Vec4::new3d(-0.5, 0.5, 2.5),
-                          Vec4::new3d(0.5, 0.5, 2.5)),
-            Triangle::new(Vec4::new3d(-0.5, -0.5, 2.5),
-                          Vec4::new3d(0.5, 0.5, 2.5),
-                          Vec4::new3d(0.5, -0.5, 2.5)),
-            // left
-            Triangle::new(Vec4::new3d(-0.5, -0.5, 1.5),
-                          Vec4::new3d(-0.5, -0.5, 2.5),
-                          Vec4::new3d(-0.5, 0.5, 2.5)),
-            Triangle::new(Vec4::new3d(-0.5, -0.5, 1.5),
-                          Vec4::new3d(-0.5, 0.5, 2.5),
-                          Vec4::new3d(-0.5, 0.5, 1.5)),
-            // right
-            Triangle::new(Vec4::new3d(0.5, -0.5, 1.5),
-                          Vec4::new3d(0.5, -0.5, 2.5),
-                          Vec4::new3d(0.5, 0.5, 2.5)),
-            Triangle::new(Vec4::new3d(0.5, -0.5, 1.5),
-                          Vec4::new3d(0.5, 0.5, 2.5),
-                          Vec4::new3d(0.5, 0.5, 1.5)),
-            // top
-            Triangle::new(Vec4::new3d(-0.5, 0.5, 1.5),
-                          Vec4::new3d(-0.5, 0.5, 2.5),
-                          Vec4::new3d(0.5, 0.5, 2.5)),
-            Triangle::new(Vec4::new3d(-0.5, 0.5, 1.5),
-                          Vec4::new3d(0.5, 0.5, 2.5),
-                          Vec4::new3d(0.5, 0.5, 1.5)),
-            // bot
-            Triangle::new(Vec4::new3d(-0.5, -0.5, 1.5),
-                          Vec4::new3d(-0.5, -0.5, 2.5),
-                          Vec4::new3d(0.5, -0.5, 2.5)),
-            Triangle::new(Vec4::new3d(-0.5, -0.5, 1.5),
-                          Vec4::new3d(0.5, -0.5, 2.5),
-                          Vec4::new3d(0.5, -0.5, 1.5)),
-        ]
+/// Triangulates the face into `indices`, reusing the vertex of every position/uv/normal combination seen before.
+fn push_face(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, vertex_indices: &mut HashMap<FaceVertex, u32>,
+             face: &[FaceVertex], data: &ObjData) {
+    let points: Vec<Vec4> = face.iter().map(|v| data.positions[v.position].clone()).collect();
+    for triangle in triangulate_polygon(&points) {
+        for corner in triangle.map(|i| face[i]) {
+            let index = *vertex_indices.entry(corner).or_insert_with(|| {
+                vertices.push(Vertex {
+                    position: data.positions[corner.position].clone(),
+                    normal: corner.normal.map(|n| data.normals[n].clone()),
+                    uv: corner.uv.map(|uv| data.uvs[uv]),
+                });
+                (vertices.len() - 1) as u32
+            });
+            indices.push(index);
+        }
     }
 }
 
-fn read_lines(path: String) -> io::Result<Lines<BufReader<File>>>
+/// Unit cube with counter-clockwise front faces, centered 2 units in front of the origin.
+pub fn cube() -> Mesh {
+    let vertices = [
+        (-0.5, -0.5, 1.5), (-0.5, 0.5, 1.5), (0.5, 0.5, 1.5), (0.5, -0.5, 1.5),
+        (-0.5, -0.5, 2.5), (-0.5, 0.5, 2.5), (0.5, 0.5, 2.5), (0.5, -0.5, 2.5),
+    ];
+    let mut mesh = Mesh::new(
+        vertices.iter().map(|&(x, y, z)| Vertex::new(Vec4::new3d(x, y, z))).collect(),
+        vec![
+            0, 1, 2, 0, 2, 3, // front
+            4, 6, 5, 4, 7, 6, // back
+            0, 4, 5, 0, 5, 1, // left
+            3, 6, 7, 3, 2, 6, // right
+            1, 5, 6, 1, 6, 2, // top
+            0, 7, 4, 0, 3, 7, // bot
+        ],
+    );
+    mesh.generate_normals(DEFAULT_CREASE_ANGLE);
+    return mesh;
+}
+
+fn read_lines(path: &str) -> io::Result<Lines<BufReader<File>>>
 {
     let file = File::open(path)?;
     Ok(BufReader::new(file).lines())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::Wrap;
+
+    fn load_source(name: &str, source: &str) -> Result<Mesh, ModelError> {
+        let path = std::env::temp_dir().join(format!("graphics_engine_{}_{}.obj", name, std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        let res = load_model(path.to_str().unwrap()).map(|model| model.mesh);
+        std::fs::remove_file(&path).unwrap();
+        return res;
+    }
+
+    #[test]
+    fn parse_errors_carry_line_numbers() {
+        let bad_number = load_source("bad_number", "v 0 0 0\nv 1 x 0\n").unwrap_err();
+        assert!(matches!(bad_number, ModelError::BadNumber { line: 2, ref token, .. } if token == "x"));
+
+        let missing_number = load_source("missing_number", "v 0 0\n").unwrap_err();
+        assert!(matches!(missing_number, ModelError::BadNumber { line: 1, .. }));
+
+        let out_of_range = load_source("out_of_range", "v 0 0 0\nv 1 0 0\n# comment\nf 1 2 3\n").unwrap_err();
+        assert!(matches!(out_of_range, ModelError::IndexOutOfRange { line: 4, index: 3, .. }));
+
+        let unsupported = load_source("unsupported", "v 0 0 0\nvx 0 1 1 2\n").unwrap_err();
+        assert!(matches!(unsupported, ModelError::UnsupportedStatement { line: 2, .. }));
+
+        assert!(matches!(load_model("does/not/exist.obj"), Err(ModelError::Io { line: 0, .. })));
+    }
+
+    #[test]
+    fn statements_without_triangles_are_ignored() {
+        let source = "mg 1\nusemap none\nv 0 0 0\nv 1 0 0\nv 0 1 0\nl 1 2\np 3\n\
+                      cstype bspline\ndeg 1\ncurv 0 1 1 2\nparm u 0 1\nend\nf 1 2 3\n";
+        assert_eq!(load_source("ignored", source).unwrap().indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn error_message_contains_location() {
+        let error = load_source("message", "v 0 0 0\nv 1 x 0\n").unwrap_err();
+        assert!(error.to_string().ends_with(".obj:2: expected a number, found 'x'"));
+    }
+
+    #[test]
+    fn concave_polygon_is_ear_clipped() {
+        // a fan from the first vertex would cover the notch at (1, 1) and flip the last triangle
+        let mesh = load_source("concave", "v 0 2 0\nv 0 0 0\nv 2 0 0\nv 2 2 0\nv 1 1 0\nf 1 2 3 4 5\n").unwrap();
+        assert_eq!(mesh.triangle_count(), 3);
+        let areas: Vec<f32> = mesh.triangles().map(|[i1, i2, i3]| {
+            let (p1, p2, p3) = (&mesh.vertices[i1].position, &mesh.vertices[i2].position, &mesh.vertices[i3].position);
+            (p2 - p1).cross_len_2d(&(p3 - p1)) / 2.0
+        }).collect();
+        assert!((areas.iter().sum::<f32>() - 3.0).abs() < 1e-5);
+        assert!(areas.iter().all(|&area| area > 0.0));
+    }
+
+    #[test]
+    fn negative_indices_and_attributes() {
+        let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 1\nf -3/-3/-1 -2/-2/-1 -1/-1/-1\nf 1//1 2//1 3//1\n";
+        let mesh = load_source("negative", source).unwrap();
+        assert_eq!(mesh.triangle_count(), 2);
+        let first: Vec<&Vertex> = mesh.indices[0..3].iter().map(|&i| &mesh.vertices[i as usize]).collect();
+        assert_eq!((first[1].position.x, first[1].position.y), (1.0, 0.0));
+        let uv = first[2].uv.unwrap();
+        assert_eq!((uv.x, uv.y), (1.0, 1.0));
+        assert_eq!(first[0].normal.as_ref().unwrap().z, 1.0);
+        let second: Vec<&Vertex> = mesh.indices[3..6].iter().map(|&i| &mesh.vertices[i as usize]).collect();
+        assert!(second.iter().all(|v| v.uv.is_none() && v.normal.is_some()));
+
+        let error = load_source("negative_out_of_range", "v 0 0 0\nf -1 -2 -3\n").unwrap_err();
+        assert!(matches!(error, ModelError::IndexOutOfRange { line: 2, index: -2, .. }));
+    }
+
+    #[test]
+    fn shared_vertices_are_stored_once() {
+        let mesh = load_source("shared", "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3\nf 1 3 4\n").unwrap();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn generated_normals_keep_hard_edges_past_crease_angle() {
+        let cube = cube();
+        assert_eq!(cube.vertices.len(), 24);
+        for vertex in &cube.vertices {
+            let normal = vertex.normal.as_ref().unwrap();
+            assert!((normal.x.abs() + normal.y.abs() + normal.z.abs() - 1.0).abs() < 1e-6);
+        }
+
+        let mut smooth = cube;
+        smooth.vertices.iter_mut().for_each(|vertex| vertex.normal = None);
+        smooth.generate_normals(180.0);
+        for vertex in &smooth.vertices {
+            let normal = vertex.normal.as_ref().unwrap();
+            assert!(normal.x != 0.0 && normal.y != 0.0 && normal.z != 0.0);
+        }
+    }
+
+    #[test]
+    fn materials_split_mesh_into_submeshes() {
+        let path = format!("{}/fixtures/models/two_materials.obj", env!("CARGO_MANIFEST_DIR"));
+        let model = load_model(&path).unwrap_or_else(|error| panic!("{}", error));
+        assert_eq!(model.mesh.submeshes, vec![Submesh { indices: 0..6, material: 0 }, Submesh { indices: 6..12, material: 1 }]);
+        assert_eq!(model.materials.len(), 2);
+
+        let checker = &model.materials[0];
+        assert_eq!((checker.diffuse.x, checker.diffuse.y, checker.diffuse.z), (0.8, 0.6, 0.4));
+        assert_eq!((checker.specular.x, checker.specular.z, checker.shininess), (0.5, 0.5, 64.0));
+        let texture = checker.diffuse_texture.as_ref().unwrap();
+        assert_eq!((texture.size(), texture.wrap), ((3, 2), Wrap::Clamp));
+
+        let glass = &model.materials[1];
+        assert_eq!((glass.opacity, glass.illumination, glass.bump_multiplier), (0.25, 1, 0.5));
+        assert_eq!(glass.bump_texture.as_ref().unwrap().size(), (32, 32));
+        assert!(glass.diffuse_texture.is_none());
+    }
+
+    #[test]
+    fn material_errors() {
+        let directory = std::env::temp_dir().join(format!("graphics_engine_mtl_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let load = |obj: &str, mtl: &str| {
+            std::fs::write(directory.join("model.mtl"), mtl).unwrap();
+            std::fs::write(directory.join("model.obj"), obj).unwrap();
+            return load_model(directory.join("model.obj").to_str().unwrap()).map(|model| model.mesh);
+        };
+        let obj = "mtllib model.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nusemtl red\nf 1 2 3\n";
+
+        let unknown = load(obj, "newmtl blue\nKd 0 0 1\n").unwrap_err();
+        assert!(matches!(unknown, ModelError::UnknownMaterial { line: 5, ref name, .. } if name == "red"));
+
+        let bad_number = load(obj, "newmtl red\nKd 1 x 0\n").unwrap_err();
+        assert!(bad_number.to_string().ends_with("model.mtl:2: expected a number, found 'x'"), "{}", bad_number);
+
+        let missing_texture = load(obj, "newmtl red\nmap_Kd missing.png\n").unwrap_err();
+        assert!(matches!(missing_texture, ModelError::Image { line: 2, .. }), "{}", missing_texture);
+
+        // faces before the first usemtl get the default material
+        let model = load("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 3\n", "").unwrap();
+        assert_eq!(model.submeshes, vec![Submesh { indices: 0..3, material: 0 }]);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
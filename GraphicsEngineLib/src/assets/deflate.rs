@@ -0,0 +1,131 @@
+//! DEFLATE (RFC 1951) compression into a zlib (RFC 1950) stream, as used by PNG. Everything goes into
+//! one block with the fixed Huffman codes, and back references are found through the last position
+//! of every three byte sequence.
+
+use crate::assets::inflate::{adler32, DISTANCE_BASES, DISTANCE_EXTRA_BITS, LENGTH_BASES, LENGTH_EXTRA_BITS};
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    /// Appends the `count` low bits of `value`, least significant bit first.
+    fn bits(&mut self, value: u32, count: u32) {
+        self.bit_buffer |= value << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.bytes.push(self.bit_buffer as u8);
+            self.bit_buffer >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    /// Appends a Huffman code, which is packed most significant bit first.
+    fn code(&mut self, code: u32, length: u32) {
+        self.bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.bytes.push(self.bit_buffer as u8);
+        }
+        return self.bytes;
+    }
+}
+
+/// Compresses `data` into a zlib stream that `zlib_decompress` and other inflaters read back.
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    // deflate with a 32K window, and check bits making the header a multiple of 31
+    writer.bytes.extend([0x78, 0x9c]);
+    // the only block is the last one and uses the fixed codes
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+
+    let hash = |position: usize| {
+        let key = u32::from_le_bytes([data[position], data[position + 1], data[position + 2], 0]);
+        return (key.wrapping_mul(0x9e3779b1) >> (32 - HASH_BITS)) as usize;
+    };
+    let mut last_positions = vec![usize::MAX; 1 << HASH_BITS];
+    let mut position = 0;
+    while position < data.len() {
+        let (mut length, mut distance) = (0, 0);
+        if position + MIN_MATCH <= data.len() {
+            let key = hash(position);
+            let candidate = last_positions[key];
+            last_positions[key] = position;
+            if candidate != usize::MAX && position - candidate <= WINDOW_SIZE {
+                let max_length = MAX_MATCH.min(data.len() - position);
+                length = (0..max_length).take_while(|&i| data[candidate + i] == data[position + i]).count();
+                distance = position - candidate;
+            }
+        }
+        if length < MIN_MATCH {
+            write_literal(&mut writer, data[position] as u32);
+            position += 1;
+            continue;
+        }
+        write_match(&mut writer, length, distance);
+        // positions inside the match are remembered too, so that later matches can start there
+        for skipped in position + 1..(position + length).min(data.len().saturating_sub(MIN_MATCH - 1)) {
+            last_positions[hash(skipped)] = skipped;
+        }
+        position += length;
+    }
+    write_literal(&mut writer, 256);
+
+    let mut bytes = writer.finish();
+    bytes.extend(adler32(data).to_be_bytes());
+    return bytes;
+}
+
+/// Writes a literal byte or the end of block symbol 256, or with larger symbols a match length.
+fn write_literal(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.code(0x30 + symbol, 8),
+        144..=255 => writer.code(0x190 + symbol - 144, 9),
+        256..=279 => writer.code(symbol - 256, 7),
+        _ => writer.code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let length_code = LENGTH_BASES.iter().rposition(|&base| base as usize <= length).unwrap();
+    write_literal(writer, 257 + length_code as u32);
+    writer.bits((length - LENGTH_BASES[length_code] as usize) as u32, LENGTH_EXTRA_BITS[length_code] as u32);
+    let distance_code = DISTANCE_BASES.iter().rposition(|&base| base as usize <= distance).unwrap();
+    writer.code(distance_code as u32, 5);
+    writer.bits((distance - DISTANCE_BASES[distance_code] as usize) as u32, DISTANCE_EXTRA_BITS[distance_code] as u32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::inflate::zlib_decompress;
+
+    #[test]
+    fn compressed_data_inflates_back() {
+        // pseudo-random bytes that hardly compress, long runs, and repeats further apart than a window
+        let mut state = 12345u32;
+        let noise: Vec<u8> = (0..70000).map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 24) as u8
+        }).collect();
+        let runs: Vec<u8> = (0..100000).map(|i| (i / 1000 % 3) as u8).collect();
+        let mut repeated = noise[..40000].to_vec();
+        repeated.extend_from_slice(&noise[..40000]);
+        for data in [vec![], b"a".to_vec(), b"hello hello hello".to_vec(), noise, runs.clone(), repeated] {
+            let compressed = zlib_compress(&data);
+            assert_eq!(zlib_decompress(&compressed, data.len()).unwrap(), data);
+        }
+        assert!(zlib_compress(&runs).len() < runs.len() / 50);
+    }
+}
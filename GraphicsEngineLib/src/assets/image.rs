@@ -0,0 +1,480 @@
+use crate::assets::png::{decode_png, encode_png};
+use crate::Color;
+use std::fmt::{Display, Formatter};
+use std::io;
+
+/// RGBA8 image with rows stored from the top.
+#[derive(Debug)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io { path: String, source: io::Error },
+    Unsupported { path: String, reason: String },
+    Corrupt { path: String, reason: String },
+}
+
+/// Failure of a decoder, which does not know the path of the file it decodes.
+pub enum DecodeError {
+    Unsupported(String),
+    Corrupt(String),
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageError::Io { path, source } => write!(f, "{}: {}", path, source),
+            ImageError::Unsupported { path, reason } => write!(f, "{}: unsupported image: {}", path, reason),
+            ImageError::Corrupt { path, reason } => write!(f, "{}: corrupt image: {}", path, reason),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for DecodeError {
+    fn from(reason: String) -> Self {
+        DecodeError::Corrupt(reason)
+    }
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Result<Self, DecodeError> {
+        if width == 0 || height == 0 {
+            return Err(DecodeError::Corrupt(String::from("image has no pixels")));
+        }
+        return Ok(Self { width, height, pixels });
+    }
+}
+
+/// Loads a PNG, BMP, PPM or TGA image. The format is detected from the file signature,
+/// except for TGA files which have none and are recognized by their extension.
+pub fn load_image(path: &str) -> Result<Image, ImageError> {
+    let bytes = std::fs::read(path).map_err(|source| ImageError::Io { path: path.to_string(), source })?;
+    let decoded = if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        decode_png(&bytes)
+    } else if bytes.starts_with(b"BM") {
+        decode_bmp(&bytes)
+    } else if bytes.starts_with(b"P3") || bytes.starts_with(b"P6") {
+        decode_ppm(&bytes)
+    } else if path.to_ascii_lowercase().ends_with(".tga") {
+        decode_tga(&bytes)
+    } else {
+        Err(DecodeError::Unsupported(String::from("unknown file format")))
+    };
+    return decoded.map_err(|error| match error {
+        DecodeError::Unsupported(reason) => ImageError::Unsupported { path: path.to_string(), reason },
+        DecodeError::Corrupt(reason) => ImageError::Corrupt { path: path.to_string(), reason },
+    });
+}
+
+pub fn rgba(red: u8, green: u8, blue: u8, alpha: u8) -> Color {
+    Color { red, green, blue, alpha }
+}
+
+fn truncated() -> DecodeError {
+    DecodeError::Corrupt(String::from("file ends unexpectedly"))
+}
+
+fn bytes_at(bytes: &[u8], start: usize, len: usize) -> Result<&[u8], DecodeError> {
+    let end = start.checked_add(len).ok_or_else(truncated)?;
+    return bytes.get(start..end).ok_or_else(truncated);
+}
+
+/// Number of pixels of an image with the size read from a header, which may be anything.
+pub fn pixel_count(width: usize, height: usize) -> Result<usize, DecodeError> {
+    if width == 0 || height == 0 {
+        return Err(DecodeError::Corrupt(format!("image size {}x{}", width, height)));
+    }
+    return width.checked_mul(height).ok_or_else(|| DecodeError::Corrupt(format!("image size {}x{}", width, height)));
+}
+
+fn u16_le(bytes: &[u8], offset: usize) -> Result<u16, DecodeError> {
+    let b = bytes_at(bytes, offset, 2)?;
+    return Ok(u16::from_le_bytes([b[0], b[1]]));
+}
+
+fn u32_le(bytes: &[u8], offset: usize) -> Result<u32, DecodeError> {
+    let b = bytes_at(bytes, offset, 4)?;
+    return Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+}
+
+/// Saves the image as PNG or binary PPM, chosen by the extension of `path`. Alpha is not saved.
+pub fn save_image(path: &str, image: &Image) -> Result<(), ImageError> {
+    let extension = path.to_ascii_lowercase();
+    let bytes = if extension.ends_with(".png") {
+        encode_png(image)
+    } else if extension.ends_with(".ppm") {
+        encode_ppm(image)
+    } else {
+        let reason = String::from("unknown file extension, expected .png or .ppm");
+        return Err(ImageError::Unsupported { path: path.to_string(), reason });
+    };
+    return std::fs::write(path, bytes).map_err(|source| ImageError::Io { path: path.to_string(), source });
+}
+
+fn encode_ppm(image: &Image) -> Vec<u8> {
+    let mut bytes = format!("P6\n{} {}\n255\n", image.width, image.height).into_bytes();
+    bytes.extend(image.pixels.iter().flat_map(|pixel| [pixel.red, pixel.green, pixel.blue]));
+    return bytes;
+}
+
+/// Decodes binary (P6) and ASCII (P3) PPM files.
+fn decode_ppm(bytes: &[u8]) -> Result<Image, DecodeError> {
+    // header fields are whitespace separated and may be interleaved with # comments
+    let mut position = 2;
+    let mut next_field = || -> Result<usize, DecodeError> {
+        loop {
+            match bytes.get(position) {
+                Some(b'#') => {
+                    while bytes.get(position).is_some_and(|&b| b != b'\n') {
+                        position += 1;
+                    }
+                }
+                Some(b) if b.is_ascii_whitespace() => position += 1,
+                Some(_) => break,
+                None => return Err(truncated()),
+            }
+        }
+        let start = position;
+        while bytes.get(position).is_some_and(|b| b.is_ascii_digit()) {
+            position += 1;
+        }
+        return std::str::from_utf8(&bytes[start..position]).ok()
+            .and_then(|field| field.parse().ok())
+            .ok_or_else(|| DecodeError::Corrupt(String::from("expected a number in the PPM data")));
+    };
+
+    let width = next_field()?;
+    let height = next_field()?;
+    let max_value = next_field()?;
+    if max_value == 0 || max_value > 255 {
+        return Err(DecodeError::Unsupported(format!("PPM maximum value {}", max_value)));
+    }
+    let scale = |value: usize| (value.min(max_value) * 255 / max_value) as u8;
+
+    let pixel_count = pixel_count(width, height)?;
+    let pixels = if bytes[1] == b'3' {
+        // every pixel takes at least five bytes, three digits and the whitespace between them
+        let mut pixels = Vec::with_capacity(pixel_count.min(bytes.len() / 5 + 1));
+        for _ in 0..pixel_count {
+            let (red, green, blue) = (next_field()?, next_field()?, next_field()?);
+            pixels.push(rgba(scale(red), scale(green), scale(blue), 255));
+        }
+        pixels
+    } else {
+        // exactly one whitespace byte separates the header from the raster
+        let data_size = pixel_count.checked_mul(3).ok_or_else(truncated)?;
+        let data = bytes_at(bytes, position + 1, data_size)?;
+        data.chunks_exact(3)
+            .map(|rgb| rgba(scale(rgb[0] as usize), scale(rgb[1] as usize), scale(rgb[2] as usize), 255))
+            .collect()
+    };
+    return Image::new(width, height, pixels);
+}
+
+/// Decodes uncompressed and RLE compressed true-color and grayscale TGA files.
+fn decode_tga(bytes: &[u8]) -> Result<Image, DecodeError> {
+    let header = bytes_at(bytes, 0, 18)?;
+    let id_length = header[0] as usize;
+    let color_map_type = header[1];
+    let image_type = header[2];
+    let width = u16_le(header, 12)? as usize;
+    let height = u16_le(header, 14)? as usize;
+    let bits_per_pixel = header[16];
+    let descriptor = header[17];
+
+    if color_map_type != 0 {
+        return Err(DecodeError::Unsupported(String::from("color mapped TGA")));
+    }
+    let (is_rle, is_gray) = match image_type {
+        2 => (false, false),
+        3 => (false, true),
+        10 => (true, false),
+        11 => (true, true),
+        _ => return Err(DecodeError::Unsupported(format!("TGA image type {}", image_type))),
+    };
+    let bytes_per_pixel = match (is_gray, bits_per_pixel) {
+        (false, 24) => 3,
+        (false, 32) => 4,
+        (true, 8) => 1,
+        _ => return Err(DecodeError::Unsupported(format!("{} bits per pixel TGA", bits_per_pixel))),
+    };
+    let read_pixel = |p: &[u8]| match p.len() {
+        1 => rgba(p[0], p[0], p[0], 255),
+        3 => rgba(p[2], p[1], p[0], 255),
+        _ => rgba(p[2], p[1], p[0], p[3]),
+    };
+
+    let mut position = 18 + id_length;
+    let pixel_count = pixel_count(width, height)?;
+    // a run length packet of one pixel value repeats it up to 128 times
+    let remaining = bytes.len().saturating_sub(position);
+    let max_pixels = if is_rle { remaining / (bytes_per_pixel + 1) * 128 } else { remaining / bytes_per_pixel };
+    let mut pixels = Vec::with_capacity(pixel_count.min(max_pixels));
+    while pixels.len() < pixel_count {
+        if !is_rle {
+            pixels.push(read_pixel(bytes_at(bytes, position, bytes_per_pixel)?));
+            position += bytes_per_pixel;
+            continue;
+        }
+        // each packet repeats one pixel or lists up to 128 raw pixels
+        let packet = *bytes.get(position).ok_or_else(truncated)?;
+        position += 1;
+        let count = (packet & 0x7f) as usize + 1;
+        if pixels.len() + count > pixel_count {
+            return Err(DecodeError::Corrupt(String::from("RLE packet runs past the end of the image")));
+        }
+        if packet & 0x80 != 0 {
+            let pixel = read_pixel(bytes_at(bytes, position, bytes_per_pixel)?);
+            position += bytes_per_pixel;
+            pixels.extend(std::iter::repeat_n(pixel, count));
+        } else {
+            for _ in 0..count {
+                pixels.push(read_pixel(bytes_at(bytes, position, bytes_per_pixel)?));
+                position += bytes_per_pixel;
+            }
+        }
+    }
+
+    // rows are stored from the bottom unless bit 5 of the descriptor is set, columns from the left unless bit 4 is
+    let mut image = Image::new(width, height, pixels)?;
+    if descriptor & 0x20 == 0 {
+        flip_rows(&mut image);
+    }
+    if descriptor & 0x10 != 0 {
+        image.pixels.chunks_exact_mut(width).for_each(|row| row.reverse());
+    }
+    return Ok(image);
+}
+
+/// Decodes uncompressed 24 and 32 bit BMP files.
+fn decode_bmp(bytes: &[u8]) -> Result<Image, DecodeError> {
+    let data_offset = u32_le(bytes, 10)? as usize;
+    let header_size = u32_le(bytes, 14)?;
+    if header_size < 40 {
+        return Err(DecodeError::Unsupported(String::from("BMP core header")));
+    }
+    let width = u32_le(bytes, 18)? as i32;
+    let height = u32_le(bytes, 22)? as i32;
+    let bits_per_pixel = u16_le(bytes, 28)?;
+    let compression = u32_le(bytes, 30)?;
+    if width <= 0 || height == 0 {
+        return Err(DecodeError::Corrupt(format!("BMP size {}x{}", width, height)));
+    }
+    // BI_RGB, or BI_BITFIELDS which 32 bit files use with the standard BGRA masks
+    if compression != 0 && !(compression == 3 && bits_per_pixel == 32) {
+        return Err(DecodeError::Unsupported(format!("BMP compression {}", compression)));
+    }
+    let bytes_per_pixel = match bits_per_pixel {
+        24 => 3,
+        32 => 4,
+        _ => return Err(DecodeError::Unsupported(format!("{} bits per pixel BMP", bits_per_pixel))),
+    };
+    if compression == 3 {
+        let masks = [u32_le(bytes, 54)?, u32_le(bytes, 58)?, u32_le(bytes, 62)?];
+        if masks != [0x00ff0000, 0x0000ff00, 0x000000ff] {
+            return Err(DecodeError::Unsupported(String::from("BMP channel masks")));
+        }
+    }
+
+    let (width, height_abs) = (width as usize, height.unsigned_abs() as usize);
+    // rows are padded to 4 bytes
+    let row_size = (width * bytes_per_pixel).div_ceil(4) * 4;
+    let remaining = bytes.len().saturating_sub(data_offset);
+    let mut pixels = Vec::with_capacity(pixel_count(width, height_abs)?.min(remaining / bytes_per_pixel));
+    for row in 0..height_abs {
+        let row_bytes = bytes_at(bytes, data_offset + row * row_size, width * bytes_per_pixel)?;
+        for p in row_bytes.chunks_exact(bytes_per_pixel) {
+            let alpha = if bytes_per_pixel == 4 { p[3] } else { 255 };
+            pixels.push(rgba(p[2], p[1], p[0], alpha));
+        }
+    }
+
+    // a positive height means the rows are stored from the bottom
+    let mut image = Image::new(width, height_abs, pixels)?;
+    if height > 0 {
+        flip_rows(&mut image);
+    }
+    return Ok(image);
+}
+
+fn flip_rows(image: &mut Image) {
+    for row in 0..image.height / 2 {
+        let mirrored = image.height - 1 - row;
+        for x in 0..image.width {
+            image.pixels.swap(row * image.width + x, mirrored * image.width + x);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::deflate::zlib_compress;
+    use crate::assets::png::crc32;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/fixtures/images/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn channels(image: &Image) -> Vec<[u8; 4]> {
+        image.pixels.iter().map(|c| [c.red, c.green, c.blue, c.alpha]).collect()
+    }
+
+    /// Top row red, green and blue, bottom row white, half transparent gray and transparent black.
+    fn pattern(with_alpha: bool) -> Vec<[u8; 4]> {
+        let alpha = |value: u8| if with_alpha { value } else { 255 };
+        return vec![[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255],
+                    [255, 255, 255, 255], [128, 128, 128, alpha(128)], [0, 0, 0, alpha(0)]];
+    }
+
+    #[test]
+    fn every_format_decodes_the_same_pattern() {
+        let cases = [
+            ("pattern_ascii.ppm", false), ("pattern_binary.ppm", false), ("pattern.tga", true),
+            ("pattern_24.bmp", false), ("pattern_32.bmp", true), ("pattern_rgb_stored.png", false),
+            ("pattern_rgba.png", true), ("pattern_palette.png", true),
+        ];
+        for (name, with_alpha) in cases {
+            let image = load_image(&fixture(name)).unwrap_or_else(|error| panic!("{}", error));
+            assert_eq!((image.width, image.height), (3, 2), "{}", name);
+            assert_eq!(channels(&image), pattern(with_alpha), "{}", name);
+        }
+
+        // run length packets: three raw pixels, two repeated white ones and a raw black one
+        let rle = load_image(&fixture("pattern_rle.tga")).unwrap();
+        let mut expected = pattern(false);
+        expected[4] = [255, 255, 255, 255];
+        assert_eq!(channels(&rle), expected);
+    }
+
+    #[test]
+    fn png_with_dynamic_huffman_blocks_and_all_filters() {
+        let image = load_image(&fixture("gradient.png")).unwrap();
+        assert_eq!((image.width, image.height), (32, 32));
+        for (x, y) in [(0, 0), (5, 7), (31, 31), (17, 30)] {
+            let expected = [(x * 8) as u8, (y * 8) as u8, (x * y % 256) as u8, 255];
+            assert_eq!(channels(&image)[y * 32 + x], expected);
+        }
+    }
+
+    #[test]
+    fn saved_images_load_back() {
+        let directory = std::env::temp_dir().join(format!("image_save_tests_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        // a gradient with flat areas, wider than it is high so that rows and columns cannot be mixed up
+        let (width, height) = (37, 11);
+        let pixels = (0..width * height).map(|i| {
+            let (x, y) = (i % width, i / width);
+            let value = if x < 10 { 200 } else { (x * 7 + y * 13) as u8 };
+            rgba(value, 255 - value, (y * 20) as u8, 0)
+        }).collect();
+        let image = Image::new(width, height, pixels).ok().unwrap();
+        for name in ["image.png", "image.PPM"] {
+            let path = directory.join(name).to_str().unwrap().to_string();
+            save_image(&path, &image).unwrap();
+            let loaded = load_image(&path).unwrap();
+            assert_eq!((loaded.width, loaded.height), (width, height), "{}", name);
+            let opaque: Vec<[u8; 4]> = channels(&image).iter().map(|&[r, g, b, _]| [r, g, b, 255]).collect();
+            assert_eq!(channels(&loaded), opaque, "{}", name);
+        }
+        let error = save_image(directory.join("image.jpg").to_str().unwrap(), &image).unwrap_err();
+        assert!(matches!(error, ImageError::Unsupported { .. }), "{}", error);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn broken_files_are_errors() {
+        let directory = std::env::temp_dir().join(format!("image_tests_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let write = |name: &str, bytes: &[u8]| {
+            let path = directory.join(name);
+            std::fs::write(&path, bytes).unwrap();
+            return path.to_str().unwrap().to_string();
+        };
+
+        let png = std::fs::read(fixture("pattern_rgba.png")).unwrap();
+        let mut flipped = png.clone();
+        flipped[40] ^= 0xff;
+        let error = load_image(&write("flipped.png", &flipped)).unwrap_err();
+        assert!(matches!(error, ImageError::Corrupt { .. }), "{}", error);
+        assert!(error.to_string().contains("CRC mismatch"), "{}", error);
+
+        let error = load_image(&write("truncated.png", &png[..png.len() - 20])).unwrap_err();
+        assert!(matches!(error, ImageError::Corrupt { .. }), "{}", error);
+
+        let bmp = std::fs::read(fixture("pattern_24.bmp")).unwrap();
+        let error = load_image(&write("truncated.bmp", &bmp[..60])).unwrap_err();
+        assert!(matches!(error, ImageError::Corrupt { .. }), "{}", error);
+
+        let mut tga = std::fs::read(fixture("pattern.tga")).unwrap();
+        tga[2] = 1;
+        let error = load_image(&write("color_mapped.tga", &tga)).unwrap_err();
+        assert!(matches!(error, ImageError::Unsupported { .. }), "{}", error);
+
+        // headers with an empty or enormous size, which must not reach the pixel loops or allocations
+        let with_size = |mut image: Vec<u8>, width_offset: usize, height_offset: usize, size: &[u8]| {
+            image[width_offset..width_offset + size.len()].copy_from_slice(size);
+            image[height_offset..height_offset + size.len()].copy_from_slice(size);
+            return image;
+        };
+        let with_png_size = |width: u32, height: u32| {
+            let mut png = with_size(png.clone(), 16, 20, &width.to_be_bytes());
+            png[20..24].copy_from_slice(&height.to_be_bytes());
+            let crc = crc32(&png[12..29]);
+            png[29..33].copy_from_slice(&crc.to_be_bytes());
+            return png;
+        };
+        let bmp_size = 0x7fffffff_u32.to_le_bytes();
+        let tga = std::fs::read(fixture("pattern.tga")).unwrap();
+        let rle_tga = std::fs::read(fixture("pattern_rle.tga")).unwrap();
+        let broken_sizes = [
+            ("empty.png", with_png_size(0, 2)),
+            ("huge.png", with_png_size(u32::MAX, u32::MAX)),
+            ("empty.ppm", b"P6\n0 2\n255\n".to_vec()),
+            ("overflowing.ppm", b"P6\n4294967296 4294967296\n255\n\0\0\0".to_vec()),
+            ("huge.ppm", b"P3\n100000 100000\n255\n1 2 3\n".to_vec()),
+            ("huge.bmp", with_size(bmp.clone(), 18, 22, &bmp_size)),
+            ("empty.tga", with_size(tga.clone(), 12, 12, &[0, 0])),
+            ("huge.tga", with_size(tga, 12, 14, &[0xff, 0xff])),
+            ("huge_rle.tga", with_size(rle_tga, 12, 14, &[0xff, 0xff])),
+        ];
+        for (name, bytes) in broken_sizes {
+            let error = load_image(&write(name, &bytes)).unwrap_err();
+            assert!(matches!(error, ImageError::Corrupt { .. }), "{}", error);
+        }
+
+        // a one pixel image whose data inflates to a megabyte stops inflating at the size of the pixel
+        let mut bomb = b"\x89PNG\r\n\x1a\n".to_vec();
+        let header = [0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0].to_vec();
+        for (kind, data) in [(b"IHDR", header), (b"IDAT", zlib_compress(&vec![0; 1 << 20])), (b"IEND", vec![])] {
+            bomb.extend((data.len() as u32).to_be_bytes());
+            let crc = crc32(&[kind.as_slice(), &data].concat());
+            bomb.extend(kind);
+            bomb.extend(data);
+            bomb.extend(crc.to_be_bytes());
+        }
+        let error = load_image(&write("bomb.png", &bomb)).unwrap_err();
+        assert!(error.to_string().contains("inflates to more than 4 bytes"), "{}", error);
+
+        let error = load_image(&write("notes.txt", b"not an image")).unwrap_err();
+        assert!(matches!(error, ImageError::Unsupported { .. }), "{}", error);
+
+        let error = load_image(&fixture("missing.png")).unwrap_err();
+        assert!(matches!(error, ImageError::Io { .. }), "{}", error);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
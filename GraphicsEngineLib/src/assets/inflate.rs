@@ -0,0 +1,312 @@
+//! DEFLATE (RFC 1951) decompression inside a zlib (RFC 1950) stream, as used by PNG.
+
+const MAX_CODE_BITS: usize = 15;
+
+/// Base lengths and extra bits for length symbols 257..285.
+pub const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+pub const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distances and extra bits for distance symbols 0..29.
+pub const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+    6145, 8193, 12289, 16385, 24577,
+];
+pub const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+/// Order in which code length code lengths are stored in a dynamic block header.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+/// Canonical Huffman code stored as the number of codes per length and the symbols sorted by code.
+struct Huffman {
+    counts: [u16; MAX_CODE_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0, bit_buffer: 0, bit_count: 0 }
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        while self.bit_count < count {
+            let Some(&byte) = self.bytes.get(self.position) else {
+                return Err(String::from("compressed data ends unexpectedly"));
+            };
+            self.position += 1;
+            self.bit_buffer |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1u64 << count) - 1) as u32;
+        self.bit_buffer = self.bit_buffer.checked_shr(count).unwrap_or(0);
+        self.bit_count -= count;
+        return Ok(value);
+    }
+
+    /// Drops the bits left in the current byte, as stored blocks start on a byte boundary.
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let bytes = self.bytes.get(self.position..self.position + count)
+            .ok_or_else(|| String::from("stored block ends unexpectedly"))?;
+        self.position += count;
+        return Ok(bytes);
+    }
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, String> {
+        let mut counts = [0u16; MAX_CODE_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // a code is only valid if it does not use more codes of some length than are available
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = left * 2 - count as i32;
+            if left < 0 {
+                return Err(String::from("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_CODE_BITS + 2];
+        for length in 1..=MAX_CODE_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        return Ok(Self { counts, symbols });
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        // codes are packed most significant bit first, so walk one bit at a time
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..=MAX_CODE_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        return Err(String::from("invalid Huffman code"));
+    }
+}
+
+/// Decompresses a zlib stream, checking its header and Adler-32 checksum. Fails as soon as the output
+/// grows beyond `max_size` bytes, so that a small crafted stream cannot expand into gigabytes.
+pub fn zlib_decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+    if data.len() < 6 {
+        return Err(String::from("zlib stream is too short"));
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+        return Err(String::from("invalid zlib header"));
+    }
+    if flg & 0x20 != 0 {
+        return Err(String::from("zlib preset dictionaries are not supported"));
+    }
+
+    let mut reader = BitReader::new(&data[2..]);
+    let out = inflate(&mut reader, max_size)?;
+    let checksum = reader.bytes(4).map_err(|_| String::from("zlib checksum is missing"))?;
+    if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(&out) {
+        return Err(String::from("zlib checksum mismatch"));
+    }
+    return Ok(out);
+}
+
+fn inflate(reader: &mut BitReader, max_size: usize) -> Result<Vec<u8>, String> {
+    let mut out = vec![];
+    loop {
+        let is_last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => inflate_stored(reader, &mut out, max_size)?,
+            1 => {
+                let (lengths, distances) = fixed_codes()?;
+                inflate_codes(reader, &mut out, max_size, &lengths, &distances)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic_codes(reader)?;
+                inflate_codes(reader, &mut out, max_size, &lengths, &distances)?;
+            }
+            _ => return Err(String::from("invalid deflate block type")),
+        }
+        if is_last {
+            reader.align_to_byte();
+            return Ok(out);
+        }
+    }
+}
+
+fn inflate_stored(reader: &mut BitReader, out: &mut Vec<u8>, max_size: usize) -> Result<(), String> {
+    reader.align_to_byte();
+    let header = reader.bytes(4)?;
+    let length = u16::from_le_bytes([header[0], header[1]]);
+    let complement = u16::from_le_bytes([header[2], header[3]]);
+    if length != !complement {
+        return Err(String::from("stored block length does not match its complement"));
+    }
+    if out.len() + length as usize > max_size {
+        return Err(too_large(max_size));
+    }
+    out.extend_from_slice(reader.bytes(length as usize)?);
+    return Ok(());
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), String> {
+    let mut lengths = [0u8; 288];
+    lengths[0..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..288].fill(8);
+    return Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?));
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let length_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if length_count > 286 || distance_count > 30 {
+        return Err(String::from("too many length or distance codes"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; length_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                if index == 0 {
+                    return Err(String::from("repeated code length without a previous one"));
+                }
+                (lengths[index - 1], 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if index + repeat > lengths.len() {
+            return Err(String::from("code lengths overflow the table"));
+        }
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(String::from("dynamic block has no end-of-block code"));
+    }
+    let (literal_lengths, distance_lengths) = lengths.split_at(length_count);
+    return Ok((Huffman::new(literal_lengths)?, Huffman::new(distance_lengths)?));
+}
+
+fn inflate_codes(reader: &mut BitReader, out: &mut Vec<u8>, max_size: usize,
+                 lengths: &Huffman, distances: &Huffman) -> Result<(), String> {
+    loop {
+        let symbol = lengths.decode(reader)? as usize;
+        if symbol < 256 {
+            if out.len() == max_size {
+                return Err(too_large(max_size));
+            }
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let length_index = symbol - 257;
+        if length_index >= LENGTH_BASES.len() {
+            return Err(String::from("invalid length symbol"));
+        }
+        let length = LENGTH_BASES[length_index] as usize + reader.bits(LENGTH_EXTRA_BITS[length_index] as u32)? as usize;
+        let distance_index = distances.decode(reader)? as usize;
+        if distance_index >= DISTANCE_BASES.len() {
+            return Err(String::from("invalid distance symbol"));
+        }
+        let distance = DISTANCE_BASES[distance_index] as usize
+            + reader.bits(DISTANCE_EXTRA_BITS[distance_index] as u32)? as usize;
+        if distance > out.len() {
+            return Err(String::from("distance points before the start of the data"));
+        }
+        if out.len() + length > max_size {
+            return Err(too_large(max_size));
+        }
+        // the copy may overlap the bytes it produces, so it has to go byte by byte
+        let start = out.len() - distance;
+        for i in 0..length {
+            out.push(out[start + i]);
+        }
+    }
+}
+
+fn too_large(max_size: usize) -> String {
+    return format!("zlib stream inflates to more than {} bytes", max_size);
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    return (b << 16) | a;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_and_fixed_blocks() {
+        // "hello hello" compressed by zlib with a fixed Huffman block and a back reference
+        let fixed = [0x78, 0xda, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x00, 0x91, 0x00, 0x19, 0x91, 0x04, 0x49];
+        assert_eq!(zlib_decompress(&fixed, 11).unwrap(), b"hello hello");
+        assert_eq!(zlib_decompress(&fixed, 10).unwrap_err(), "zlib stream inflates to more than 10 bytes");
+        assert_eq!(zlib_decompress(&fixed, 3).unwrap_err(), "zlib stream inflates to more than 3 bytes");
+
+        // the same text as a single stored block
+        let mut stored = vec![0x78, 0x01, 0x01, 11, 0, !11, !0];
+        stored.extend_from_slice(b"hello hello");
+        stored.extend_from_slice(&adler32(b"hello hello").to_be_bytes());
+        assert_eq!(zlib_decompress(&stored, 11).unwrap(), b"hello hello");
+        assert!(zlib_decompress(&stored, 10).is_err());
+
+        let mut corrupt = stored.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert_eq!(zlib_decompress(&corrupt, 11).unwrap_err(), "zlib checksum mismatch");
+    }
+}
@@ -0,0 +1,125 @@
+use crate::assets::image::load_image;
+use crate::assets::{read_lines, LineLocation, ModelError};
+use crate::lighting::Material;
+use crate::math::Vec3;
+use crate::texture::{Filter, Texture, Wrap};
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::SplitWhitespace;
+use std::sync::Arc;
+
+/// Textures already loaded for a model, keyed by file path and wrap mode, so materials sharing an image share the texture.
+pub type TextureCache = HashMap<(String, bool), Arc<Texture>>;
+
+/// Options of a `map_*` statement that the renderer uses, together with the image path.
+struct TextureMap {
+    path: String,
+    clamp: bool,
+    bump_multiplier: Option<f32>,
+}
+
+/// Parses the materials of an MTL file. Texture paths are relative to the MTL file.
+pub fn load_materials(path: &str, textures: &mut TextureCache) -> Result<Vec<(String, Material)>, ModelError> {
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+    let mut materials: Vec<(String, Material)> = vec![];
+    let lines = read_lines(path).map_err(|source| ModelError::Io { path: path.to_string(), line: 0, source })?;
+    for (line_ind, line) in lines.enumerate() {
+        let location = LineLocation { path, line: line_ind + 1 };
+        let line = line.map_err(|source| ModelError::Io { path: path.to_string(), line: location.line, source })?;
+        let mut line_tokens = line.split_whitespace();
+        let statement = match line_tokens.next() {
+            Some(comment) if comment.starts_with('#') => continue,
+            Some(statement) => statement,
+            None => continue,
+        };
+        if statement == "newmtl" {
+            let name = line_tokens.collect::<Vec<&str>>().join(" ");
+            materials.push((name, Material::default()));
+            continue;
+        }
+        let Some((_, material)) = materials.last_mut() else {
+            return Err(location.unsupported(&format!("{} before newmtl", statement)));
+        };
+        match statement {
+            "Ka" => material.ambient = location.color(line_tokens)?,
+            "Kd" => material.diffuse = location.color(line_tokens)?,
+            "Ks" => material.specular = location.color(line_tokens)?,
+            "Ns" => material.shininess = location.parse(line_tokens.next())?,
+            "d" => material.opacity = location.parse(line_tokens.next())?,
+            "Tr" => material.opacity = 1.0 - location.parse::<f32>(line_tokens.next())?,
+            "illum" => material.illumination = location.parse(line_tokens.next())?,
+            "map_Kd" => {
+                let map = location.texture_map(line_tokens)?;
+                material.diffuse_texture = Some(location.texture(directory, &map, textures)?);
+            }
+            "map_Bump" | "map_bump" | "bump" => {
+                let map = location.texture_map(line_tokens)?;
+                material.bump_multiplier = map.bump_multiplier.unwrap_or(1.0);
+                material.bump_texture = Some(location.texture(directory, &map, textures)?);
+            }
+            // statements for features the renderer does not have
+            "Ke" | "Ni" | "Tf" | "sharpness" | "map_Ka" | "map_Ks" | "map_Ns" | "map_d" | "disp" | "decal" | "refl" => {}
+            statement => return Err(location.unsupported(statement)),
+        }
+    }
+    return Ok(materials);
+}
+
+impl LineLocation<'_> {
+    /// Parses `r [g b]`, where a single value is used for all three channels.
+    fn color(&self, mut tokens: SplitWhitespace) -> Result<Vec3, ModelError> {
+        let red = self.parse(tokens.next())?;
+        let Some(green) = tokens.next() else {
+            return Ok(Vec3::new(red, red, red));
+        };
+        return Ok(Vec3::new(red, self.parse(Some(green))?, self.parse(tokens.next())?));
+    }
+
+    /// Parses `[-option values...]... file`, where the file name may contain spaces.
+    fn texture_map(&self, tokens: SplitWhitespace) -> Result<TextureMap, ModelError> {
+        let tokens: Vec<&str> = tokens.collect();
+        let mut map = TextureMap { path: String::new(), clamp: false, bump_multiplier: None };
+        let mut i = 0;
+        while i < tokens.len() && tokens[i].starts_with('-') {
+            let option = tokens[i];
+            i += 1;
+            match option {
+                "-clamp" => {
+                    map.clamp = tokens.get(i) == Some(&"on");
+                    i += 1;
+                }
+                "-bm" => {
+                    map.bump_multiplier = Some(self.parse(tokens.get(i).copied())?);
+                    i += 1;
+                }
+                "-blendu" | "-blendv" | "-boost" | "-texres" | "-imfchan" | "-type" | "-cc" => i += 1,
+                "-mm" => i += 2,
+                // offset, scale and turbulence take one to three numbers
+                "-o" | "-s" | "-t" => {
+                    while tokens.get(i).is_some_and(|token| token.parse::<f32>().is_ok()) {
+                        i += 1;
+                    }
+                }
+                option => return Err(self.unsupported(option)),
+            }
+        }
+        map.path = tokens.get(i..).unwrap_or_default().join(" ");
+        if map.path.is_empty() {
+            return Err(self.unsupported("texture map without a file"));
+        }
+        return Ok(map);
+    }
+
+    fn texture(&self, directory: &Path, map: &TextureMap, textures: &mut TextureCache) -> Result<Arc<Texture>, ModelError> {
+        let path = directory.join(&map.path).to_string_lossy().into_owned();
+        if let Some(texture) = textures.get(&(path.clone(), map.clamp)) {
+            return Ok(texture.clone());
+        }
+        let image = load_image(&path)
+            .map_err(|source| ModelError::Image { path: self.path.to_string(), line: self.line, source })?;
+        let wrap = if map.clamp { Wrap::Clamp } else { Wrap::Repeat };
+        let texture = Arc::new(Texture::new(image.width, image.height, &image.pixels, Filter::Trilinear, wrap));
+        textures.insert((path, map.clamp), texture.clone());
+        return Ok(texture);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::assets::deflate::zlib_compress;
+use crate::assets::image::{pixel_count, rgba, DecodeError, Image};
+use crate::assets::inflate::zlib_decompress;
+use crate::Color;
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    /// Bytes between a pixel and the one it is filtered against, at least one for sub-byte depths.
+    fn filter_stride(&self) -> usize {
+        (self.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    fn row_size(&self) -> usize {
+        (self.width * self.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+}
+
+/// Decodes a non-interlaced PNG of any color type and bit depth. 16 bit channels keep their high byte.
+pub fn decode_png(bytes: &[u8]) -> Result<Image, DecodeError> {
+    let mut header = None;
+    let mut palette: Vec<Color> = vec![];
+    let mut transparency: Vec<u8> = vec![];
+    let mut compressed = vec![];
+
+    let mut position = 8;
+    loop {
+        let length = bytes.get(position..position + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or_else(|| DecodeError::Corrupt(String::from("PNG has no IEND chunk")))?;
+        let chunk = bytes.get(position + 4..position + 8 + length)
+            .ok_or_else(|| DecodeError::Corrupt(String::from("PNG chunk ends unexpectedly")))?;
+        let stored_crc = bytes.get(position + 8 + length..position + 12 + length)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| DecodeError::Corrupt(String::from("PNG chunk ends unexpectedly")))?;
+        position += 12 + length;
+
+        let (kind, data) = chunk.split_at(4);
+        if crc32(chunk) != stored_crc {
+            return Err(DecodeError::Corrupt(format!("CRC mismatch in {} chunk", String::from_utf8_lossy(kind))));
+        }
+        match kind {
+            b"IHDR" => header = Some(read_header(data)?),
+            b"PLTE" => palette = data.chunks_exact(3).map(|c| rgba(c[0], c[1], c[2], 255)).collect(),
+            b"tRNS" => transparency = data.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            // lowercase first letter: ancillary chunk that can be skipped
+            _ if kind[0].is_ascii_lowercase() => {}
+            _ => return Err(DecodeError::Unsupported(format!("critical chunk {}", String::from_utf8_lossy(kind)))),
+        }
+    }
+
+    let header = header.ok_or_else(|| DecodeError::Corrupt(String::from("PNG has no IHDR chunk")))?;
+    if header.color_type == 3 && palette.is_empty() {
+        return Err(DecodeError::Corrupt(String::from("palette image without a PLTE chunk")));
+    }
+    for (entry, &alpha) in palette.iter_mut().zip(&transparency) {
+        entry.alpha = alpha;
+    }
+
+    // the filtered rows, each with its filter type byte in front
+    let filtered_size = (header.row_size() + 1).checked_mul(header.height)
+        .ok_or_else(|| DecodeError::Corrupt(String::from("PNG image data is too large")))?;
+    let filtered = zlib_decompress(&compressed, filtered_size)?;
+    let raw = unfilter(&header, &filtered)?;
+    let pixels = to_rgba(&header, &raw, &palette, &transparency)?;
+    return Image::new(header.width, header.height, pixels);
+}
+
+/// Encodes the image as an 8 bit RGB PNG, leaving out alpha, which rendered images do not fill.
+pub fn encode_png(image: &Image) -> Vec<u8> {
+    let mut filtered = Vec::with_capacity((image.width * 3 + 1) * image.height);
+    for row in image.pixels.chunks_exact(image.width) {
+        // the Sub filter turns runs of one color into runs of zeros, which compress well
+        filtered.push(1);
+        let mut left = [0u8; 3];
+        for pixel in row {
+            let rgb = [pixel.red, pixel.green, pixel.blue];
+            filtered.extend([0, 1, 2].map(|i| rgb[i].wrapping_sub(left[i])));
+            left = rgb;
+        }
+    }
+    let mut header = vec![];
+    header.extend((image.width as u32).to_be_bytes());
+    header.extend((image.height as u32).to_be_bytes());
+    // bit depth, RGB color type, and the only compression, filter and interlace methods
+    header.extend([8, 2, 0, 0, 0]);
+
+    let mut bytes = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, data) in [(b"IHDR", header), (b"IDAT", zlib_compress(&filtered)), (b"IEND", vec![])] {
+        bytes.extend((data.len() as u32).to_be_bytes());
+        let chunk_start = bytes.len();
+        bytes.extend(kind);
+        bytes.extend(data);
+        let crc = crc32(&bytes[chunk_start..]);
+        bytes.extend(crc.to_be_bytes());
+    }
+    return bytes;
+}
+
+fn read_header(data: &[u8]) -> Result<Header, DecodeError> {
+    if data.len() != 13 {
+        return Err(DecodeError::Corrupt(String::from("IHDR chunk has the wrong size")));
+    }
+    let width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let (bit_depth, color_type, interlace) = (data[8], data[9], data[12]);
+    let valid_depths: &[u8] = match color_type {
+        0 => &[1, 2, 4, 8, 16],
+        3 => &[1, 2, 4, 8],
+        2 | 4 | 6 => &[8, 16],
+        _ => return Err(DecodeError::Corrupt(format!("PNG color type {}", color_type))),
+    };
+    if !valid_depths.contains(&bit_depth) {
+        return Err(DecodeError::Corrupt(format!("bit depth {} with color type {}", bit_depth, color_type)));
+    }
+    if data[10] != 0 || data[11] != 0 {
+        return Err(DecodeError::Unsupported(String::from("PNG compression or filter method")));
+    }
+    if interlace != 0 {
+        return Err(DecodeError::Unsupported(String::from("interlaced PNG")));
+    }
+    pixel_count(width, height)?;
+    return Ok(Header { width, height, bit_depth, color_type });
+}
+
+/// Reverses the per-row filters, returning the rows without their filter type bytes.
+fn unfilter(header: &Header, filtered: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let row_size = header.row_size();
+    let stride = header.filter_stride();
+    if (row_size + 1).checked_mul(header.height).is_none_or(|size| filtered.len() < size) {
+        return Err(DecodeError::Corrupt(String::from("PNG image data is too short")));
+    }
+
+    let mut raw = vec![0u8; row_size * header.height];
+    for y in 0..header.height {
+        let filter = filtered[y * (row_size + 1)];
+        let source = &filtered[y * (row_size + 1) + 1..(y + 1) * (row_size + 1)];
+        let (previous_rows, current) = raw.split_at_mut(y * row_size);
+        let current = &mut current[..row_size];
+        let above = if y == 0 { None } else { Some(&previous_rows[(y - 1) * row_size..]) };
+        for x in 0..row_size {
+            let left = if x >= stride { current[x - stride] } else { 0 };
+            let up = above.map_or(0, |row| row[x]);
+            let up_left = if x >= stride { above.map_or(0, |row| row[x - stride]) } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(DecodeError::Corrupt(format!("PNG filter type {}", filter))),
+            };
+            current[x] = source[x].wrapping_add(predicted);
+        }
+    }
+    return Ok(raw);
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (to_left, to_up, to_up_left) =
+        ((estimate - left as i16).abs(), (estimate - up as i16).abs(), (estimate - up_left as i16).abs());
+    if to_left <= to_up && to_left <= to_up_left {
+        return left;
+    }
+    if to_up <= to_up_left {
+        return up;
+    }
+    return up_left;
+}
+
+fn to_rgba(header: &Header, raw: &[u8], palette: &[Color], transparency: &[u8]) -> Result<Vec<Color>, DecodeError> {
+    let row_size = header.row_size();
+    let depth = header.bit_depth as usize;
+    let channels = header.channels();
+    // a tRNS chunk on a gray or RGB image names the one color that is fully transparent
+    let transparent_key: Vec<u16> = transparency.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+
+    let mut pixels = Vec::with_capacity(header.width * header.height);
+    for row in raw.chunks_exact(row_size) {
+        for x in 0..header.width {
+            let sample = |channel: usize| -> u16 {
+                let index = x * channels + channel;
+                match depth {
+                    16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+                    8 => row[index] as u16,
+                    _ => {
+                        let bit = index * depth;
+                        ((row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8) as u16
+                    }
+                }
+            };
+            // scales a sample of any depth to 8 bits
+            let to_u8 = |value: u16| -> u8 {
+                match depth {
+                    16 => (value >> 8) as u8,
+                    _ => (value as u32 * 255 / ((1 << depth) - 1)) as u8,
+                }
+            };
+            let samples: Vec<u16> = (0..channels).map(sample).collect();
+            let is_keyed = transparent_key.len() == channels && transparent_key == samples;
+            let key_alpha = if is_keyed { 0 } else { 255 };
+            let pixel = match header.color_type {
+                0 => {
+                    let gray = to_u8(samples[0]);
+                    rgba(gray, gray, gray, key_alpha)
+                }
+                2 => rgba(to_u8(samples[0]), to_u8(samples[1]), to_u8(samples[2]), key_alpha),
+                3 => *palette.get(samples[0] as usize)
+                    .ok_or_else(|| DecodeError::Corrupt(format!("palette index {} out of range", samples[0])))?,
+                4 => {
+                    let gray = to_u8(samples[0]);
+                    rgba(gray, gray, gray, to_u8(samples[1]))
+                }
+                _ => rgba(to_u8(samples[0]), to_u8(samples[1]), to_u8(samples[2]), to_u8(samples[3])),
+            };
+            pixels.push(pixel);
+        }
+    }
+    return Ok(pixels);
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    return !crc;
+}
@@ -0,0 +1,23 @@
+#![allow(clippy::needless_return)]
+
+use graphics_engine::cli::{run, CliError, USAGE};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    return match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Usage(reason)) => {
+            eprintln!("error: {}\n\n{}", reason, USAGE);
+            ExitCode::from(2)
+        }
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    };
+}
@@ -0,0 +1,222 @@
+//! The `render-image` command, which renders an OBJ model into a PNG or PPM file without a window.
+
+use crate::assets::image::{save_image, Image, ImageError};
+use crate::assets::{load_model, ModelError};
+use crate::game::Scene;
+use crate::math::{Quat, Transform, Vec3};
+use crate::render::{render, DepthBuffer, ScreenSize};
+use crate::available_threads;
+use std::fmt::{Display, Formatter};
+
+pub const USAGE: &str = "\
+Usage: render-image [options] <model.obj> <output.png|output.ppm>
+
+Renders the model, placed at the origin, as seen from a camera looking along its +z axis with +y up.
+
+Options:
+  --size <width>x<height>  image size in pixels, at most 64 megapixels [default: 640x480]
+  --position <x,y,z>       camera position [default: 0,0,-3]
+  --rotation <x,y,z>       camera rotation in degrees around the x axis, then the y
+                           axis, then the z axis [default: 0,0,0]
+  --fov <degrees>          vertical field of view [default: 60]
+  --far <distance>         distance of the far clipping plane [default: 100]
+  --threads <count>        rendering threads, 0 for one per core [default: 0]
+  --wireframe              draw the triangle edges on top of the surfaces
+  -h, --help               print this help";
+
+/// Largest image, whose depth buffer takes half a gigabyte.
+const MAX_PIXEL_COUNT: i32 = 64 << 20;
+
+#[derive(Debug)]
+pub enum CliError {
+    /// The command line is invalid, and the usage should be shown.
+    Usage(String),
+    Model(ModelError),
+    Image(ImageError),
+}
+
+/// Settings of one run, parsed from the command line.
+#[derive(Debug)]
+struct Options {
+    model: String,
+    output: String,
+    screen_size: ScreenSize,
+    position: Vec3,
+    rotation: Vec3,
+    vertical_fov: f32,
+    z_far: f32,
+    thread_count: usize,
+    wireframe: bool,
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Usage(reason) => write!(f, "{}", reason),
+            CliError::Model(error) => write!(f, "{}", error),
+            CliError::Image(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for CliError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CliError::Usage(_) => None,
+            CliError::Model(error) => Some(error),
+            CliError::Image(error) => Some(error),
+        }
+    }
+}
+
+impl Options {
+    /// Parses the arguments following the program name.
+    fn parse(args: &[String]) -> Result<Self, CliError> {
+        let mut options = Options {
+            model: String::new(),
+            output: String::new(),
+            screen_size: ScreenSize { width: 640, height: 480 },
+            position: Vec3::new(0.0, 0.0, -3.0),
+            rotation: Vec3::default(),
+            vertical_fov: 60.0,
+            z_far: 100.0,
+            thread_count: 0,
+            wireframe: false,
+        };
+        let mut paths = vec![];
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                paths.push(arg.clone());
+                continue;
+            }
+            if arg == "--wireframe" {
+                options.wireframe = true;
+                continue;
+            }
+            let value = args.next().ok_or_else(|| CliError::Usage(format!("{} needs a value", arg)))?;
+            match arg.as_str() {
+                "--size" => {
+                    let size = value.split_once('x')
+                        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                        .filter(|&(width, height): &(i32, i32)| {
+                            width > 0 && height > 0 && width.checked_mul(height).is_some_and(|count| count <= MAX_PIXEL_COUNT)
+                        });
+                    let (width, height) = size.ok_or_else(|| invalid_value(arg, value))?;
+                    options.screen_size = ScreenSize { width, height };
+                }
+                "--position" => options.position = parse_vec3(arg, value)?,
+                "--rotation" => options.rotation = parse_vec3(arg, value)?,
+                "--fov" => options.vertical_fov = parse_number(arg, value, |fov| fov > 0.0 && fov < 180.0)?,
+                "--far" => options.z_far = parse_number(arg, value, |distance| distance > 0.1)?,
+                "--threads" => options.thread_count = value.parse().map_err(|_| invalid_value(arg, value))?,
+                _ => return Err(CliError::Usage(format!("unknown option {}", arg))),
+            }
+        }
+        let [model, output]: [String; 2] = paths.try_into()
+            .map_err(|_| CliError::Usage(String::from("expected a model and an output path")))?;
+        options.model = model;
+        options.output = output;
+        return Ok(options);
+    }
+}
+
+fn invalid_value(option: &str, value: &str) -> CliError {
+    return CliError::Usage(format!("invalid value {:?} for {}", value, option));
+}
+
+fn parse_number(option: &str, value: &str, is_valid: impl Fn(f32) -> bool) -> Result<f32, CliError> {
+    return value.parse().ok().filter(|&number: &f32| number.is_finite() && is_valid(number))
+        .ok_or_else(|| invalid_value(option, value));
+}
+
+fn parse_vec3(option: &str, value: &str) -> Result<Vec3, CliError> {
+    let components: Vec<f32> = value.split(',')
+        .map(|component| component.trim().parse().ok().filter(|number: &f32| number.is_finite()))
+        .collect::<Option<_>>()
+        .ok_or_else(|| invalid_value(option, value))?;
+    let [x, y, z] = components[..] else {
+        return Err(invalid_value(option, value));
+    };
+    return Ok(Vec3::new(x, y, z));
+}
+
+/// Runs the command with the arguments following the program name.
+pub fn run(args: &[String]) -> Result<(), CliError> {
+    let options = Options::parse(args)?;
+    let image = render_model(&options)?;
+    return save_image(&options.output, &image).map_err(CliError::Image);
+}
+
+fn render_model(options: &Options) -> Result<Image, CliError> {
+    let model = load_model(&options.model).map_err(CliError::Model)?;
+    let mut scene = Scene::new(model.mesh, model.materials);
+    scene.camera.vertical_fov = options.vertical_fov;
+    scene.camera.z_far = options.z_far;
+    let model_node = scene.node_mut(0).unwrap();
+    model_node.transform = Transform::default();
+    model_node.object.as_mut().unwrap().wireframe = options.wireframe;
+    scene.node_mut(scene.camera_node).unwrap().transform = Transform {
+        translation: options.position,
+        rotation: Quat::from_euler(&options.rotation),
+        ..Transform::default()
+    };
+
+    let mut buffer = DepthBuffer::new(options.screen_size);
+    let thread_count = if options.thread_count == 0 { available_threads() } else { options.thread_count };
+    render(&mut buffer, &scene, thread_count);
+    return Ok(buffer.to_image());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::image::load_image;
+    use crate::Color;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn options_are_parsed_and_checked() {
+        let options = Options::parse(&args("--size 320x200 model.obj --position 1,2.5,-4 out.png --wireframe")).unwrap();
+        assert_eq!(options.model, "model.obj");
+        assert_eq!(options.output, "out.png");
+        assert_eq!(options.screen_size, ScreenSize { width: 320, height: 200 });
+        assert_eq!((options.position.x, options.position.y, options.position.z), (1.0, 2.5, -4.0));
+        assert_eq!((options.vertical_fov, options.z_far, options.thread_count), (60.0, 100.0, 0));
+        assert!(options.wireframe);
+
+        for invalid in ["model.obj", "a.obj b.png c.png", "--size 0x10 a.obj b.png", "--size 10 a.obj b.png",
+                        "--size 50000x50000 a.obj b.png", "--size 8193x8193 a.obj b.png",
+                        "--fov 180 a.obj b.png", "--position 1,2 a.obj b.png", "--rotation 1,x,3 a.obj b.png",
+                        "--threads -1 a.obj b.png", "--zoom 2 a.obj b.png", "a.obj b.png --far"] {
+            let error = Options::parse(&args(invalid)).unwrap_err();
+            assert!(matches!(error, CliError::Usage(_)), "{}: {}", invalid, error);
+        }
+    }
+
+    #[test]
+    fn model_is_rendered_into_a_file() {
+        let directory = std::env::temp_dir().join(format!("cli_tests_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let model = format!("{}/fixtures/models/two_materials.obj", env!("CARGO_MANIFEST_DIR"));
+        let output = directory.join("quads.png").to_str().unwrap().to_string();
+        // the two quads span x in 0..2 and y in 0..1 and face +z, where the camera looks back at them
+        let command = format!("{} {} --size 64x40 --position 1,0.5,2 --rotation 0,180,0 --threads 2", model, output);
+        run(&args(&command)).unwrap();
+
+        let image = load_image(&output).unwrap();
+        assert_eq!((image.width, image.height), (64, 40));
+        let is_background = |color: &Color| (color.red, color.green, color.blue) == (200, 50, 0);
+        assert!(is_background(&image.pixels[0]));
+        assert!(!is_background(&image.pixels[20 * 64 + 32]));
+
+        let missing = format!("{}/missing.obj {}", directory.to_str().unwrap(), output);
+        assert!(matches!(run(&args(&missing)), Err(CliError::Model(_))));
+        let unknown_format = format!("{} {}", model, directory.join("quads.gif").to_str().unwrap());
+        assert!(matches!(run(&args(&unknown_format)), Err(CliError::Image(_))));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
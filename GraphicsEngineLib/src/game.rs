@@ -1,74 +1,249 @@
-use crate::assets::load_model;
-use crate::math::{Mat3x3, Mesh, Vec3};
-use crate::render::Camera;
+use crate::math::{Mat4x4, Mesh, Quat, Transform, Vec3};
+use crate::lighting::{Light, Material, Shading, Shadow};
+use crate::render::{Camera, CullMode, FrontFace};
 use crate::UserInput;
 
+/// Index of a node in its scene, which stays valid until the node is removed.
+pub type NodeId = usize;
+
 pub struct GameObject {
     pub mesh: Mesh,
-    pub position: Vec3,
-    pub rotation: Vec3,
+    pub shading: Shading,
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    /// Whether the triangle edges and corners are drawn on top of the shaded surface.
+    pub wireframe: bool,
+    /// Indexed by `Submesh::material` of the mesh.
+    pub materials: Vec<Material>,
+}
+
+/// Motion applied to a node every frame.
+#[derive(Clone, Copy, Debug)]
+pub enum Behavior {
+    /// Turns around `axis` of the parent space by `speed` degrees per second.
+    Spin { axis: Vec3, speed: f32 },
+}
+
+pub struct Node {
+    pub name: String,
+    /// Placement relative to the parent node, or to the world for nodes without a parent.
+    pub transform: Transform,
+    /// What is drawn at the node, nothing for nodes that only group or place others.
+    pub object: Option<GameObject>,
+    pub light: Option<Light>,
+    /// Shadows cast by a directional or spot light of the node, ignored for point lights.
+    pub shadow: Option<Shadow>,
+    pub behaviors: Vec<Behavior>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
 }
 
 pub struct Scene {
     pub camera: Camera,
-    pub directional_light_rotation: Vec3,
-    pub objects: Vec<GameObject>,
+    /// Node placing the camera, which looks along its +z axis with +y up.
+    pub camera_node: NodeId,
+    /// Light reaching every surface from all directions, scaled by the material's ambient color.
+    pub ambient_color: Vec3,
+    /// Slots of removed nodes stay empty so that the ids of the other nodes do not change.
+    nodes: Vec<Option<Node>>,
+}
+
+impl GameObject {
+    pub fn new(mesh: Mesh, materials: Vec<Material>) -> GameObject {
+        GameObject {
+            mesh,
+            shading: Shading::Phong,
+            cull_mode: CullMode::Back,
+            front_face: FrontFace::CounterClockwise,
+            wireframe: true,
+            materials,
+        }
+    }
 }
 
 impl Scene {
-    pub fn new() -> Scene {
-        let mesh = load_model(String::from("model.obj"));
-        let scene = Scene {
+    pub fn new(mesh: Mesh, materials: Vec<Material>) -> Scene {
+        let mut scene = Scene {
             camera: Camera {
                 vertical_fov: 60.0,
                 z_near: 0.1,
                 z_far: 5.0,
-                position: Vec3::new(0.0, 0.0, -1.0),
-                rotation: Vec3::new(0.0, 0.0, 0.0),
             },
-            directional_light_rotation: Vec3::new(-30.0, 0.0, 0.0),
-            objects: vec![GameObject {
-                mesh,
-                position: Vec3::new(0.0, 0.0, -1.0),
-                rotation: Vec3::new(0.0, 0.0, 0.0),
-            }],
+            camera_node: 0,
+            ambient_color: Vec3::new(1.0, 1.0, 1.0),
+            nodes: vec![],
+        };
+        let transform = Transform::from_translation(Vec3::new(0.0, 0.0, -1.0));
+        scene.add_node("model", transform, Some(GameObject::new(mesh, materials)), None);
+        scene.camera_node = scene.add_node("camera", transform, None, None);
+
+        // white sunlight from above and behind the model, circling around it
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let sun_rotation = Quat::look_rotation(&Vec3::new(0.0, -0.5, -0.75_f32.sqrt()), &up);
+        let sun = scene.add_node("sun", Transform { rotation: sun_rotation, ..Transform::default() }, None, None);
+        let sun = scene.node_mut(sun).unwrap();
+        sun.light = Some(Light::Directional { color: Vec3::new(1.0, 1.0, 1.0), intensity: 1.0 });
+        sun.behaviors.push(Behavior::Spin { axis: up, speed: 90.0 });
+        return scene;
+    }
+
+    /// Adds a node under `parent`, or at the root if it is None or not in the scene.
+    pub fn add_node(&mut self, name: &str, transform: Transform, object: Option<GameObject>,
+                    parent: Option<NodeId>) -> NodeId {
+        let id = self.nodes.len();
+        let parent = parent.filter(|&parent| self.node(parent).is_some());
+        self.nodes.push(Some(Node {
+            name: name.to_string(),
+            transform,
+            object,
+            light: None,
+            shadow: None,
+            behaviors: vec![],
+            parent,
+            children: vec![],
+        }));
+        if let Some(parent) = parent {
+            self.nodes[parent].as_mut().unwrap().children.push(id);
+        }
+        return id;
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(id).and_then(|node| node.as_ref())
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.nodes.get_mut(id).and_then(|node| node.as_mut())
+    }
+
+    /// Object drawn at the node, if the node exists and draws one.
+    pub fn object_mut(&mut self, id: NodeId) -> Option<&mut GameObject> {
+        self.node_mut(id).and_then(|node| node.object.as_mut())
+    }
+
+    /// The first node with the name, in the order the nodes were added.
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes.iter().position(|node| node.as_ref().is_some_and(|node| node.name == name))
+    }
+
+    /// Moves the node with its children under `parent`, or to the root if it is None. The local
+    /// transform is kept, so the node moves along with its new parent. Returns false if either node
+    /// is not in the scene or the node would become its own ancestor.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> bool {
+        if self.node(id).is_none() {
+            return false;
+        }
+        let mut ancestor = parent;
+        while let Some(ancestor_id) = ancestor {
+            let Some(ancestor_node) = self.node(ancestor_id) else {
+                return false;
+            };
+            if ancestor_id == id {
+                return false;
+            }
+            ancestor = ancestor_node.parent;
+        }
+
+        if let Some(old_parent) = self.nodes[id].as_ref().unwrap().parent {
+            self.nodes[old_parent].as_mut().unwrap().children.retain(|&child| child != id);
+        }
+        if let Some(parent) = parent {
+            self.nodes[parent].as_mut().unwrap().children.push(id);
+        }
+        self.nodes[id].as_mut().unwrap().parent = parent;
+        return true;
+    }
+
+    /// Removes the node together with all its descendants. Returns false if the node is not in the
+    /// scene or the camera node would be removed.
+    pub fn remove(&mut self, id: NodeId) -> bool {
+        let Some(node) = self.node(id) else {
+            return false;
         };
-        scene
+        let mut subtree = vec![id];
+        let mut i = 0;
+        while i < subtree.len() {
+            subtree.extend(&self.nodes[subtree[i]].as_ref().unwrap().children);
+            i += 1;
+        }
+        if subtree.contains(&self.camera_node) {
+            return false;
+        }
+
+        if let Some(parent) = node.parent {
+            self.nodes[parent].as_mut().unwrap().children.retain(|&child| child != id);
+        }
+        for removed in subtree {
+            self.nodes[removed] = None;
+        }
+        return true;
+    }
+
+    /// World matrix of every node, indexed by node id, found by walking down from the root nodes
+    /// once. Empty slots get the identity.
+    pub fn world_matrices(&self) -> Vec<Mat4x4> {
+        let mut matrices: Vec<Mat4x4> = self.nodes.iter().map(|_| Mat4x4::identity()).collect();
+        let mut pending: Vec<NodeId> = (0..self.nodes.len())
+            .filter(|&id| self.node(id).is_some_and(|node| node.parent.is_none()))
+            .collect();
+        while let Some(id) = pending.pop() {
+            let node = self.nodes[id].as_ref().unwrap();
+            let local = node.transform.matrix();
+            matrices[id] = match node.parent {
+                Some(parent) => &matrices[parent] * &local,
+                None => local,
+            };
+            pending.extend(&node.children);
+        }
+        return matrices;
+    }
+
+    /// Nodes that emit light, with their ids.
+    pub fn lights(&self) -> impl Iterator<Item = (NodeId, &Light)> {
+        self.nodes.iter().enumerate()
+            .filter_map(|(id, node)| node.as_ref().and_then(|node| node.light.as_ref()).map(|light| (id, light)))
+    }
+
+    /// Nodes that draw an object, with their ids.
+    pub fn objects(&self) -> impl Iterator<Item = (NodeId, &GameObject)> {
+        self.nodes.iter().enumerate()
+            .filter_map(|(id, node)| node.as_ref().and_then(|node| node.object.as_ref()).map(|object| (id, object)))
     }
 }
 
 pub fn update_scene(scene: &mut Scene, user_input: &UserInput, delta_time: f32) {
     update_camera(scene, user_input, delta_time);
-    // update_object(scene, delta_time);
+    update_behaviors(scene, delta_time);
 }
 
-fn update_object(scene: &mut Scene, delta_time: f32) {
-    // let mut offset = Vec3::new(0.0, 0.0, 1.0);
-    // offset *= 0.5 * delta_time;
-    // let mut pos = &mut scene.objects[0].position;
-    // pos += &offset;
-
-    // let rotation_offset = 45.0 * delta_time;
-    // let mut rotation = &mut scene.objects[0].rotation;
-    // rotation += &Vec3::new(0.0, 0.0, rotation_offset);
+fn update_behaviors(scene: &mut Scene, delta_time: f32) {
+    for node in scene.nodes.iter_mut().flatten() {
+        for behavior in &node.behaviors {
+            match behavior {
+                Behavior::Spin { axis, speed } => {
+                    node.transform.rotate(&Quat::from_axis_angle(axis, speed * delta_time));
+                }
+            }
+        }
+    }
 }
 
 fn update_camera(scene: &mut Scene, user_input: &UserInput, delta_time: f32) {
-    let mut camera_pos = &mut scene.camera.position;
-    let mut camera_rotation = &mut scene.camera.rotation;
+    let Some(camera_node) = scene.node_mut(scene.camera_node) else {
+        return;
+    };
+    let camera_transform = &mut camera_node.transform;
 
     let mut camera_offset = camera_movement_dir(user_input);
-    camera_offset *= &Mat3x3::rotation(camera_rotation);
+    camera_offset *= &camera_transform.rotation.to_mat3();
     camera_offset *= 0.5 * delta_time;
+    let mut camera_pos = &mut camera_transform.translation;
     camera_pos += &camera_offset;
 
+    // turning around the y axis of the parent space keeps the horizon level
     let mut camera_rotation_offset = camera_rotation_dir(user_input);
     camera_rotation_offset *= 15.0 * delta_time;
-    camera_rotation += &camera_rotation_offset;
-
-    // scene.directional_light_rotation.x += 15.0 * delta_time;
-    scene.directional_light_rotation.y += 90.0 * delta_time;
-    // scene.directional_light_rotation.z += 35.0 * delta_time;
+    camera_transform.rotate(&Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), camera_rotation_offset.y));
 }
 
 fn camera_movement_dir(user_input: &UserInput) -> Vec3 {
@@ -104,3 +279,61 @@ fn camera_rotation_dir(user_input: &UserInput) -> Vec3 {
     }
     return dir;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::cube;
+    use crate::math::Vec4;
+
+    fn world_position(scene: &Scene, id: NodeId) -> Vec3 {
+        let position = &scene.world_matrices()[id] * &Vec4::new3d(0.0, 0.0, 0.0);
+        return Vec3::new(position.x, position.y, position.z);
+    }
+
+    fn assert_near(actual: Vec3, expected: Vec3) {
+        assert!((&actual - &expected).len() < 1e-5, "{:?} is not {:?}", actual, expected);
+    }
+
+    #[test]
+    fn children_follow_their_parents() {
+        let mut scene = Scene::new(cube(), vec![Material::default()]);
+        let car = scene.add_node("car", Transform {
+            translation: Vec3::new(10.0, 0.0, 0.0),
+            rotation: Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), 90.0),
+            scale: Vec3::new(2.0, 2.0, 2.0),
+        }, None, None);
+        let wheel = scene.add_node("wheel", Transform::from_translation(Vec3::new(1.0, 0.0, 0.0)), None, Some(car));
+        let object = GameObject::new(cube(), vec![Material::default()]);
+        let hubcap = scene.add_node("hubcap", Transform::from_translation(Vec3::new(0.0, 0.0, 0.5)), Some(object), Some(wheel));
+        assert_eq!(scene.find("wheel"), Some(wheel));
+        assert_eq!(scene.find("tree"), None);
+        assert_near(world_position(&scene, wheel), Vec3::new(10.0, 0.0, -2.0));
+        assert_near(world_position(&scene, hubcap), Vec3::new(11.0, 0.0, -2.0));
+        assert_eq!(scene.objects().map(|(id, _)| id).collect::<Vec<_>>(), vec![0, hubcap]);
+
+        // moving the car moves the whole subtree
+        scene.node_mut(car).unwrap().transform.translation.y = 3.0;
+        assert_near(world_position(&scene, hubcap), Vec3::new(11.0, 3.0, -2.0));
+
+        // a reparented node keeps its local transform
+        assert!(scene.set_parent(wheel, Some(scene.camera_node)));
+        assert_eq!(scene.node(wheel).unwrap().parent, Some(scene.camera_node));
+        assert!(scene.node(car).unwrap().children.is_empty());
+        assert_near(world_position(&scene, wheel), Vec3::new(1.0, 0.0, -1.0));
+        assert!(!scene.set_parent(scene.camera_node, Some(hubcap)));
+        assert!(!scene.set_parent(wheel, Some(wheel)));
+        assert!(!scene.set_parent(wheel, Some(100)));
+        assert!(scene.set_parent(wheel, None));
+        assert_near(world_position(&scene, hubcap), Vec3::new(1.0, 0.0, 0.5));
+
+        // removing a node removes its children, and the ids of the others stay the same
+        assert!(scene.remove(wheel));
+        assert!(scene.node(wheel).is_none() && scene.node(hubcap).is_none());
+        assert!(!scene.remove(wheel));
+        assert_eq!(scene.find("car"), Some(car));
+        assert!(scene.set_parent(scene.camera_node, Some(car)));
+        assert!(!scene.remove(car));
+        assert_eq!(scene.objects().count(), 1);
+    }
+}
@@ -0,0 +1,175 @@
+//! Golden-image tests, which render canonical scenes and compare them against the reference images in
+//! `fixtures/golden`. When a scene differs, the rendered image and a diff image showing the differing
+//! pixels in magenta over the faded reference are written to `target/golden`.
+//!
+//! After an intended change of the output, `BLESS_GOLDEN=1 cargo test golden` writes the new references.
+
+use crate::assets::cube;
+use crate::assets::image::{load_image, save_image, Image};
+use crate::game::Scene;
+use crate::lighting::Material;
+use crate::math::{Mesh, Quat, Submesh, Transform, Vec3, Vec4, Vertex};
+use crate::render::{render, CullMode, DepthBuffer, ScreenSize};
+use crate::Color;
+
+const SCREEN_SIZE: ScreenSize = ScreenSize { width: 96, height: 72 };
+
+/// Largest difference of a color channel from the reference that still counts as a match. References
+/// blessed on one platform are checked on others, whose `powf`, `sin` and `cos` may round differently.
+const CHANNEL_TOLERANCE: u8 = 2;
+
+/// Pixels allowed to differ by more than `CHANNEL_TOLERANCE`, for triangle edges that rounding moves
+/// by a pixel.
+const MAX_MISMATCHED_PIXELS: usize = 4;
+
+const BLESS_VARIABLE: &str = "BLESS_GOLDEN";
+
+struct Difference {
+    mismatched_pixels: usize,
+    max_channel_difference: u8,
+    diff: Image,
+}
+
+fn diff_images(actual: &Image, expected: &Image) -> Difference {
+    let mut mismatched_pixels = 0;
+    let mut max_channel_difference = 0;
+    let pixels = actual.pixels.iter().zip(&expected.pixels).map(|(actual, expected)| {
+        let channel_difference = [
+            actual.red.abs_diff(expected.red),
+            actual.green.abs_diff(expected.green),
+            actual.blue.abs_diff(expected.blue),
+        ].into_iter().max().unwrap();
+        max_channel_difference = max_channel_difference.max(channel_difference);
+        if channel_difference > CHANNEL_TOLERANCE {
+            mismatched_pixels += 1;
+            return Color { red: 255, green: 0, blue: 255, alpha: 255 };
+        }
+        let gray = ((expected.red as u32 + expected.green as u32 + expected.blue as u32) / 12) as u8;
+        return Color { red: gray, green: gray, blue: gray, alpha: 255 };
+    }).collect();
+    let diff = Image { width: expected.width, height: expected.height, pixels };
+    return Difference { mismatched_pixels, max_channel_difference, diff };
+}
+
+/// Compares `image` against the reference `name`, or replaces the reference when `BLESS_GOLDEN` is 1.
+fn check_golden(name: &str, image: &Image) {
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    let reference_path = format!("{}/fixtures/golden/{}.png", manifest_dir, name);
+    if std::env::var_os(BLESS_VARIABLE).is_some_and(|value| value == "1") {
+        std::fs::create_dir_all(format!("{}/fixtures/golden", manifest_dir)).unwrap();
+        save_image(&reference_path, image).unwrap();
+        return;
+    }
+
+    let reference = load_image(&reference_path)
+        .unwrap_or_else(|error| panic!("{}, run `{}=1 cargo test golden` to create it", error, BLESS_VARIABLE));
+    let output_dir = format!("{}/target/golden", manifest_dir);
+    let actual_path = format!("{}/{}.png", output_dir, name);
+    let save_actual = || {
+        std::fs::create_dir_all(&output_dir).unwrap();
+        save_image(&actual_path, image).unwrap();
+    };
+    if (image.width, image.height) != (reference.width, reference.height) {
+        save_actual();
+        panic!("{}: rendered {}x{} instead of {}x{}, see {}",
+               name, image.width, image.height, reference.width, reference.height, actual_path);
+    }
+    let difference = diff_images(image, &reference);
+    if difference.mismatched_pixels <= MAX_MISMATCHED_PIXELS {
+        return;
+    }
+    save_actual();
+    let diff_path = format!("{}/{}.diff.png", output_dir, name);
+    save_image(&diff_path, &difference.diff).unwrap();
+    panic!("{}: {} pixels differ from the reference by up to {}, see {} and {}",
+           name, difference.mismatched_pixels, difference.max_channel_difference, actual_path, diff_path);
+}
+
+fn render_scene(scene: &Scene) -> Image {
+    let mut buffer = DepthBuffer::new(SCREEN_SIZE);
+    render(&mut buffer, scene, 2);
+    return buffer.to_image();
+}
+
+/// Places the model at the origin and the camera at `eye`, looking at `target`.
+fn place(scene: &mut Scene, model: Transform, eye: Vec3, target: Vec3) {
+    scene.node_mut(0).unwrap().transform = model;
+    scene.node_mut(scene.camera_node).unwrap().transform = Transform {
+        translation: eye,
+        rotation: Quat::look_rotation(&(&target - &eye), &Vec3::new(0.0, 1.0, 0.0)),
+        ..Transform::default()
+    };
+}
+
+/// Material showing its diffuse color without lighting.
+fn unlit(red: f32, green: f32, blue: f32) -> Material {
+    return Material { diffuse: Vec3::new(red, green, blue), illumination: 0, ..Material::default() };
+}
+
+#[test]
+fn cube_is_lit_and_outlined() {
+    // the default scene with its wireframe, seen from above the front right corner of the cube
+    let mut scene = Scene::new(cube(), vec![Material::default()]);
+    place(&mut scene, Transform::default(), Vec3::new(1.6, 1.2, 0.4), Vec3::new(0.0, 0.0, 2.0));
+    check_golden("cube", &render_scene(&scene));
+}
+
+#[test]
+fn cube_crossing_the_near_plane_is_clipped() {
+    // the corner closest to the camera lies in front of the near plane and is cut off, opening a view
+    // of the inside faces
+    let mut scene = Scene::new(cube(), vec![Material::default()]);
+    let object = scene.object_mut(0).unwrap();
+    object.cull_mode = CullMode::None;
+    object.wireframe = false;
+    place(&mut scene, Transform::default(), Vec3::new(0.55, 0.55, 1.45), Vec3::new(0.0, 0.0, 2.0));
+    check_golden("clipped_cube", &render_scene(&scene));
+}
+
+#[test]
+fn model_crossing_every_frustum_side_is_clipped() {
+    // a cube of 4 units whose closest corner is cut off by the near plane, spreading past all edges
+    // of the screen and beyond the far plane
+    let mut scene = Scene::new(cube(), vec![Material::default()]);
+    scene.camera.z_far = 2.5;
+    scene.object_mut(0).unwrap().wireframe = false;
+    let model = Transform {
+        translation: Vec3::new(0.0, 0.0, -8.0),
+        rotation: Quat::from_euler(&Vec3::new(0.0, 0.0, 20.0)),
+        scale: Vec3::new(4.0, 4.0, 4.0),
+    };
+    place(&mut scene, model, Vec3::new(1.22, 2.62, -2.05), Vec3::new(0.3, -0.2, 0.0));
+    check_golden("frustum_sides", &render_scene(&scene));
+}
+
+#[test]
+fn intersecting_triangles_are_depth_tested() {
+    // red and green triangles leaning in opposite directions cross halfway, in front of a blue one
+    let vertices = [
+        (-1.0, -0.8, 1.5), (1.0, -0.8, 2.5), (0.0, 0.9, 2.0),
+        (-1.0, 0.8, 2.5), (0.0, -0.9, 2.0), (1.0, 0.8, 1.5),
+        (-0.6, -0.6, 2.2), (0.6, -0.6, 2.2), (0.0, 0.6, 2.2),
+    ];
+    let mut mesh = Mesh::new(
+        vertices.iter().map(|&(x, y, z)| Vertex::new(Vec4::new3d(x, y, z))).collect(),
+        (0..9).collect(),
+    );
+    mesh.submeshes = (0..3).map(|material| Submesh { indices: material * 3..material * 3 + 3, material }).collect();
+    let mut scene = Scene::new(mesh, vec![unlit(0.9, 0.1, 0.1), unlit(0.1, 0.8, 0.1), unlit(0.1, 0.2, 0.9)]);
+    let object = scene.object_mut(0).unwrap();
+    object.cull_mode = CullMode::None;
+    object.wireframe = false;
+    place(&mut scene, Transform::default(), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+    check_golden("depth_test", &render_scene(&scene));
+}
+
+#[test]
+fn diff_marks_pixels_outside_the_tolerance() {
+    let color = |red: u8| Color { red, green: 120, blue: 60, alpha: 255 };
+    let expected = Image { width: 3, height: 1, pixels: vec![color(90), color(90), color(90)] };
+    let actual = Image { width: 3, height: 1, pixels: vec![color(90), color(90 + CHANNEL_TOLERANCE), color(100)] };
+    let difference = diff_images(&actual, &expected);
+    assert_eq!((difference.mismatched_pixels, difference.max_channel_difference), (1, 10));
+    let is_marked = |pixel: &Color| (pixel.red, pixel.green, pixel.blue) == (255, 0, 255);
+    assert_eq!(difference.diff.pixels.iter().map(is_marked).collect::<Vec<_>>(), [false, false, true]);
+}
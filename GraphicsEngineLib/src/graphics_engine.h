@@ -15,6 +15,106 @@ typedef struct {
     bool shift_pressed;
 } UserInput;
 
-extern void create_scene(void);
-extern Color* update_and_render(int32_t width, int32_t height, UserInput user_input, float delta_time);
-extern void free_bitmap(Color* array, size_t length);
\ No newline at end of file
+typedef enum {
+    SHADING_FLAT,
+    SHADING_GOURAUD,
+    SHADING_PHONG,
+} Shading;
+
+typedef enum {
+    FILTER_NEAREST,
+    FILTER_BILINEAR,
+    FILTER_TRILINEAR,
+} Filter;
+
+typedef enum {
+    WRAP_REPEAT,
+    WRAP_CLAMP,
+    WRAP_MIRROR,
+} Wrap;
+
+typedef enum {
+    CULL_MODE_NONE,
+    CULL_MODE_BACK,
+    CULL_MODE_FRONT,
+} CullMode;
+
+typedef enum {
+    FRONT_FACE_COUNTER_CLOCKWISE,
+    FRONT_FACE_CLOCKWISE,
+} FrontFace;
+
+typedef struct {
+    size_t culled_triangles;
+} RenderStats;
+
+typedef enum {
+    LIGHT_TYPE_DIRECTIONAL,
+    LIGHT_TYPE_POINT,
+    LIGHT_TYPE_SPOT,
+} LightType;
+
+// Fields a light type does not use are ignored. Position and direction are relative to the parent node,
+// attenuation divides the strength by constant + linear * d + quadratic * d^2 at distance d, and the
+// spot cone half angles are in degrees.
+typedef struct {
+    LightType light_type;
+    float color[3];
+    float intensity;
+    float position[3];
+    float direction[3];
+    float attenuation[3];
+    float inner_angle;
+    float outer_angle;
+} LightDesc;
+
+typedef struct Engine* EngineHandle;
+
+extern EngineHandle engine_create(void);
+extern void engine_destroy(EngineHandle engine);
+// Returns the error message of the most recent call, or NULL if it succeeded or does not describe its
+// failures, valid until the next call into the engine.
+extern const char* engine_last_error(EngineHandle engine);
+// Scene nodes are addressed by id, which is also the object index of the functions below.
+// The loaded model is node 0, the camera node 1 and the sun node 2. Returns -1 if no node has the name.
+extern ptrdiff_t engine_find_node(EngineHandle engine, const char* name);
+// Attaches the node to `parent`, or to the root if `parent` is negative, keeping its local transform.
+extern bool engine_set_node_parent(EngineHandle engine, size_t node, ptrdiff_t parent);
+// Removes the node together with its children. The camera cannot be removed.
+extern bool engine_remove_node(EngineHandle engine, size_t node);
+// Adds a light node under `parent`, or at the root if `parent` is negative. Returns its id, or -1 if
+// the direction is zero or the attenuation factors are negative, not finite or all zero.
+// On failure see engine_last_error.
+extern ptrdiff_t engine_add_light(EngineHandle engine, const LightDesc* light, ptrdiff_t parent);
+// Makes the directional or spot light of the node cast shadows from a resolution x resolution depth map,
+// or stops it if resolution is 0. `bias` is a world-space offset along the surface normal that prevents
+// self-shadowing, and `pcf_radius` texels around the lookup are averaged to soften the shadow edges.
+// The resolution is at most 4096 and `pcf_radius` at most 16.
+extern bool engine_set_light_shadow(EngineHandle engine, size_t node, int32_t resolution, float bias,
+                                    int32_t pcf_radius);
+// Splits the camera view into `cascades` shadow maps of the node's directional light by distance, keeping
+// near shadows sharp over long views, with at most 4 cascades. `debug` tints the surfaces by the cascade
+// they use.
+extern bool engine_set_light_shadow_cascades(EngineHandle engine, size_t node, size_t cascades, bool debug);
+// Scales the node along its own axes before rotating and moving it; negative factors mirror it.
+extern bool engine_set_node_scale(EngineHandle engine, size_t node, float x, float y, float z);
+extern bool engine_set_object_shading(EngineHandle engine, size_t object_index, Shading shading);
+extern bool engine_set_object_culling(EngineHandle engine, size_t object_index, CullMode cull_mode,
+                                      FrontFace front_face);
+// Draws the triangle edges and corners over the object's surface, which is on by default.
+extern bool engine_set_object_wireframe(EngineHandle engine, size_t object_index, bool wireframe);
+// Counters of the last rendered frame.
+extern RenderStats engine_last_render_stats(EngineHandle engine);
+// Number of threads rasterizing screen tiles, 0 for one per core. The image is the same for any count.
+extern bool engine_set_thread_count(EngineHandle engine, size_t thread_count);
+// Sets the texture of all the object's materials. Pixels are stored row by row from the top;
+// NULL pixels remove the texture.
+extern bool engine_set_object_texture(EngineHandle engine, size_t object_index, const Color* pixels,
+                                      int32_t width, int32_t height, Filter filter, Wrap wrap);
+// Loads a PNG, BMP, PPM or TGA file as the texture of all the object's materials.
+// On failure see engine_last_error.
+extern bool engine_load_object_texture(EngineHandle engine, size_t object_index, const char* path,
+                                       Filter filter, Wrap wrap);
+// Writes `height` rows of `width` pixels into `pixels`, consecutive rows start `stride` pixels apart.
+extern bool engine_update_and_render(EngineHandle engine, UserInput user_input, float delta_time,
+                                     Color* pixels, int32_t width, int32_t height, int32_t stride);
\ No newline at end of file
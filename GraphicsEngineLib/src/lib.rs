@@ -1,11 +1,26 @@
+#![allow(clippy::needless_return)]
+
+use crate::assets::image::load_image;
+use crate::assets::{cube, load_model};
 use crate::game::{update_scene, Scene};
-use crate::render::{render, ScreenSize};
-use std::ptr::null_mut;
+use std::ffi::{c_char, CStr, CString};
+use std::ptr::null;
+use crate::lighting::{Attenuation, Light, Material, SceneLighting, Shading, Shadow, MAX_PCF_RADIUS,
+                      MAX_SHADOW_CASCADES, MAX_SHADOW_RESOLUTION};
+use crate::math::{Quat, Transform, Vec3};
+use crate::render::{render_reusing_shadow_maps, CullMode, DepthBuffer, FrontFace, RenderStats, ScreenSize};
+use crate::texture::{Filter, Texture, Wrap};
+use std::sync::Arc;
 
 mod render;
 mod math;
 mod game;
 mod assets;
+mod lighting;
+mod texture;
+pub mod cli;
+#[cfg(test)]
+mod golden;
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
@@ -28,58 +43,626 @@ pub struct Color {
     alpha: u8,
 }
 
-static mut SCENE: *mut Scene = null_mut();
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub enum LightType {
+    Directional,
+    Point,
+    Spot,
+}
+
+/// Light added with `engine_add_light`. Fields a light type does not use are ignored.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct LightDesc {
+    light_type: LightType,
+    color: [f32; 3],
+    intensity: f32,
+    /// Relative to the parent node, for point and spot lights.
+    position: [f32; 3],
+    /// Direction the light shines along relative to the parent node, for directional and spot lights.
+    direction: [f32; 3],
+    /// Constant, linear and quadratic factors dividing the strength of point and spot lights at a distance.
+    attenuation: [f32; 3],
+    /// Half angles of the spot cone in degrees, at full strength inside the inner one.
+    inner_angle: f32,
+    outer_angle: f32,
+}
+
+/// All state owned by one engine instance. The host only ever sees it as an opaque `EngineHandle`.
+pub struct Engine {
+    scene: Scene,
+    depth_buffer: Option<DepthBuffer>,
+    /// Lighting of the last frame, whose shadow maps are drawn over again in the next one.
+    lighting: Option<SceneLighting>,
+    last_error: Option<CString>,
+    last_stats: RenderStats,
+    /// Number of threads rasterizing screen tiles, at least one.
+    thread_count: usize,
+}
+
+pub type EngineHandle = *mut Engine;
+
+impl Engine {
+    fn new() -> Self {
+        let mut engine = Self {
+            scene: Scene::new(cube(), vec![Material::default()]),
+            depth_buffer: None,
+            lighting: None,
+            last_error: None,
+            last_stats: RenderStats::default(),
+            thread_count: available_threads(),
+        };
+        match load_model("model.obj") {
+            Ok(model) => engine.scene = Scene::new(model.mesh, model.materials),
+            Err(error) => engine.set_error(error.to_string()),
+        }
+        return engine;
+    }
+
+    fn set_error(&mut self, message: String) {
+        self.last_error = Some(CString::new(message.replace('\0', "")).unwrap());
+    }
+
+    /// Renders the scene into the depth buffer, which is only reallocated when the screen size changes,
+    /// and likewise the shadow maps when their resolution changes.
+    fn render(&mut self, screen_size: ScreenSize) -> &DepthBuffer {
+        let depth_buffer = match self.depth_buffer.take() {
+            Some(buffer) if buffer.screen_size() == screen_size => buffer,
+            _ => DepthBuffer::new(screen_size),
+        };
+        let depth_buffer = self.depth_buffer.insert(depth_buffer);
+        self.last_stats =
+            render_reusing_shadow_maps(depth_buffer, &self.scene, self.thread_count, &mut self.lighting);
+        return depth_buffer;
+    }
+}
+
+/// Returns the engine behind `engine` with the error of the previous call cleared, so that
+/// `engine_last_error` always describes the most recent call.
+unsafe fn start_call<'a>(engine: EngineHandle) -> Option<&'a mut Engine> {
+    let engine = engine.as_mut()?;
+    engine.last_error = None;
+    return Some(engine);
+}
+
+fn available_threads() -> usize {
+    return std::thread::available_parallelism().map_or(1, |count| count.get());
+}
 
 #[no_mangle]
-pub extern "C" fn create_scene() {
-    unsafe {
-        SCENE = Box::into_raw(Box::new(Scene::new()));
+pub extern "C" fn engine_create() -> EngineHandle {
+    Box::into_raw(Box::new(Engine::new()))
+}
+
+/// # Safety
+/// `engine` must be null or a handle returned by `engine_create` that was not destroyed yet.
+#[no_mangle]
+pub unsafe extern "C" fn engine_destroy(engine: EngineHandle) {
+    if engine.is_null() {
+        return;
     }
+    drop(Box::from_raw(engine));
 }
 
+/// Returns the message of the error reported by the most recent call into the engine, or null if that
+/// call succeeded or does not describe its failures. Right after `engine_create` it describes why the
+/// model could not be loaded. The string stays valid until the next call into the engine.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`.
 #[no_mangle]
-pub extern "C" fn update_and_render(width: i32, height: i32, user_input: UserInput, delta_time: f32) -> *mut Color {
-    let mut scene = unsafe {
-        Box::from_raw(SCENE)
+pub unsafe extern "C" fn engine_last_error(engine: EngineHandle) -> *const c_char {
+    let Some(engine) = engine.as_ref() else {
+        return null();
     };
-    update_scene(&mut scene, &user_input, delta_time);
-    let screen_size = ScreenSize { width, height };
-    let mut bitmap = render(screen_size, &scene);
+    return engine.last_error.as_ref().map_or(null(), |message| message.as_ptr());
+}
 
-    let bitmap_ptr = bitmap.as_mut_ptr();
-    std::mem::forget(bitmap);
-    std::mem::forget(scene);
-    return bitmap_ptr;
+/// Returns the id of the first scene node called `name`, or -1 if there is none. Node ids are the
+/// object indices of the other functions; the loaded model is node 0, the camera node 1 and the sun node 2.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`, and `name` must be null or
+/// a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn engine_find_node(engine: EngineHandle, name: *const c_char) -> isize {
+    let Some(engine) = start_call(engine) else {
+        return -1;
+    };
+    if name.is_null() {
+        return -1;
+    }
+    let name = CStr::from_ptr(name).to_string_lossy();
+    return engine.scene.find(&name).map_or(-1, |id| id as isize);
 }
 
+/// Attaches the node to `parent`, or detaches it to the root if `parent` is negative. The node keeps
+/// its transform relative to the parent. Returns false if a node does not exist or the node would
+/// become its own ancestor.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`.
 #[no_mangle]
-pub extern "C" fn free_bitmap(arr: *mut Color, length: usize) {
-    if arr.is_null() {
-        return;
+pub unsafe extern "C" fn engine_set_node_parent(engine: EngineHandle, node: usize, parent: isize) -> bool {
+    let Some(engine) = start_call(engine) else {
+        return false;
+    };
+    return engine.scene.set_parent(node, usize::try_from(parent).ok());
+}
+
+/// Removes the node and all nodes attached to it. Returns false if the node does not exist or the
+/// camera is among them.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`.
+#[no_mangle]
+pub unsafe extern "C" fn engine_remove_node(engine: EngineHandle, node: usize) -> bool {
+    let Some(engine) = start_call(engine) else {
+        return false;
+    };
+    return engine.scene.remove(node);
+}
+
+/// Scales the node along its own axes, which may differ, before it is rotated and moved. Negative
+/// factors mirror it. Returns false if the node does not exist or a factor is zero or not finite.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`.
+#[no_mangle]
+pub unsafe extern "C" fn engine_set_node_scale(engine: EngineHandle, node: usize, x: f32, y: f32, z: f32) -> bool {
+    let Some(node) = start_call(engine).and_then(|engine| engine.scene.node_mut(node)) else {
+        return false;
+    };
+    if [x, y, z].iter().any(|factor| *factor == 0.0 || !factor.is_finite()) {
+        return false;
+    }
+    node.transform.scale = Vec3::new(x, y, z);
+    return true;
+}
+
+/// Adds a light node under `parent`, or at the root if `parent` is negative, and returns its id.
+/// Returns -1 if `light` is null, the direction is zero or the attenuation factors are negative, not
+/// finite or all zero, in which case `engine_last_error` describes why.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`, and `light` must be null or
+/// point to a valid `LightDesc`.
+#[no_mangle]
+pub unsafe extern "C" fn engine_add_light(engine: EngineHandle, light: *const LightDesc, parent: isize) -> isize {
+    let Some(engine) = start_call(engine) else {
+        return -1;
+    };
+    let Some(desc) = light.as_ref() else {
+        engine.set_error(String::from("light description is null"));
+        return -1;
+    };
+    let vec3 = |[x, y, z]: [f32; 3]| Vec3::new(x, y, z);
+    let (color, direction) = (vec3(desc.color), vec3(desc.direction));
+    if direction.len() == 0.0 {
+        engine.set_error(String::from("light direction is zero"));
+        return -1;
+    }
+    let [constant, linear, quadratic] = desc.attenuation;
+    let attenuation = Attenuation { constant, linear, quadratic };
+    if !attenuation.is_valid() {
+        engine.set_error(format!("light attenuation {:?} is negative, not finite or all zero", desc.attenuation));
+        return -1;
+    }
+    let intensity = desc.intensity;
+    let light = match desc.light_type {
+        LightType::Directional => Light::Directional { color, intensity },
+        LightType::Point => Light::Point { color, intensity, attenuation },
+        LightType::Spot => Light::Spot {
+            color,
+            intensity,
+            attenuation,
+            inner_angle: desc.inner_angle,
+            outer_angle: desc.outer_angle,
+        },
+    };
+    // a direction along the up axis has no defined roll, so another up axis is used for it
+    let up = if direction.x == 0.0 && direction.z == 0.0 { Vec3::new(0.0, 0.0, 1.0) } else { Vec3::new(0.0, 1.0, 0.0) };
+    let transform = Transform {
+        translation: vec3(desc.position),
+        rotation: Quat::look_rotation(&direction, &up),
+        ..Transform::default()
+    };
+    let id = engine.scene.add_node("light", transform, None, usize::try_from(parent).ok());
+    engine.scene.node_mut(id).unwrap().light = Some(light);
+    return id as isize;
+}
+
+/// Makes the directional or spot light of the node cast shadows, looked up in a depth map of
+/// `resolution` by `resolution` texels. `bias` moves shaded points along their normal before the
+/// lookup, and the depth tests of `pcf_radius` texels around it are averaged to soften the edges.
+/// A zero resolution turns the shadows off. Returns false if the node has no such light, an argument
+/// is negative, `resolution` is above 4096 or `pcf_radius` is above 16.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`.
+#[no_mangle]
+pub unsafe extern "C" fn engine_set_light_shadow(engine: EngineHandle, node: usize, resolution: i32, bias: f32,
+                                                 pcf_radius: i32) -> bool {
+    let Some(node) = start_call(engine).and_then(|engine| engine.scene.node_mut(node)) else {
+        return false;
+    };
+    if !matches!(node.light, Some(Light::Directional { .. } | Light::Spot { .. })) {
+        return false;
     }
-    unsafe {
-        Vec::from_raw_parts(arr, length, length);
+    let is_valid = (0..=MAX_SHADOW_RESOLUTION).contains(&resolution) && bias >= 0.0 && bias.is_finite()
+        && (0..=MAX_PCF_RADIUS).contains(&pcf_radius);
+    if !is_valid {
+        return false;
     }
+    let (cascades, debug_cascades) = node.shadow.map_or((1, false), |shadow| (shadow.cascades, shadow.debug_cascades));
+    node.shadow = (resolution > 0).then_some(Shadow { resolution, bias, pcf_radius, cascades, debug_cascades });
+    return true;
+}
+
+/// Splits the view of the camera into `cascades` slices by distance, each with its own shadow map of
+/// the node's directional light, so that shadows near the camera stay sharp over long views. With
+/// `debug` set, surfaces are tinted by the index of the cascade they use. Returns false if the node has
+/// no directional light casting shadows or `cascades` is not between 1 and 4.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`.
+#[no_mangle]
+pub unsafe extern "C" fn engine_set_light_shadow_cascades(engine: EngineHandle, node: usize, cascades: usize,
+                                                          debug: bool) -> bool {
+    let Some(node) = start_call(engine).and_then(|engine| engine.scene.node_mut(node)) else {
+        return false;
+    };
+    let (Some(Light::Directional { .. }), Some(shadow)) = (node.light, node.shadow.as_mut()) else {
+        return false;
+    };
+    if !(1..=MAX_SHADOW_CASCADES).contains(&cascades) {
+        return false;
+    }
+    shadow.cascades = cascades;
+    shadow.debug_cascades = debug;
+    return true;
+}
+
+/// Selects how the object at `object_index` is lit. Returns false if there is no such object.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`.
+#[no_mangle]
+pub unsafe extern "C" fn engine_set_object_shading(engine: EngineHandle, object_index: usize, shading: Shading) -> bool {
+    let Some(object) = start_call(engine).and_then(|engine| engine.scene.object_mut(object_index)) else {
+        return false;
+    };
+    object.shading = shading;
+    return true;
+}
+
+/// Selects which triangles of the object at `object_index` are culled and which winding faces the
+/// front. Returns false if there is no such object.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`.
+#[no_mangle]
+pub unsafe extern "C" fn engine_set_object_culling(engine: EngineHandle, object_index: usize, cull_mode: CullMode,
+                                                   front_face: FrontFace) -> bool {
+    let Some(object) = start_call(engine).and_then(|engine| engine.scene.object_mut(object_index)) else {
+        return false;
+    };
+    object.cull_mode = cull_mode;
+    object.front_face = front_face;
+    return true;
+}
+
+/// Selects whether the triangle edges and corners of the object at `object_index` are drawn over its
+/// surface, which they are by default. Returns false if there is no such object.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`.
+#[no_mangle]
+pub unsafe extern "C" fn engine_set_object_wireframe(engine: EngineHandle, object_index: usize, wireframe: bool) -> bool {
+    let Some(object) = start_call(engine).and_then(|engine| engine.scene.object_mut(object_index)) else {
+        return false;
+    };
+    object.wireframe = wireframe;
+    return true;
+}
+
+/// Returns the counters of the last rendered frame, all zero before the first frame or for a null `engine`.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`.
+#[no_mangle]
+pub unsafe extern "C" fn engine_last_render_stats(engine: EngineHandle) -> RenderStats {
+    return engine.as_ref().map_or(RenderStats::default(), |engine| engine.last_stats);
+}
+
+/// Sets how many threads rasterize the screen tiles, where zero uses one thread per available core
+/// and one renders on the calling thread only. The image does not depend on the thread count.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`.
+#[no_mangle]
+pub unsafe extern "C" fn engine_set_thread_count(engine: EngineHandle, thread_count: usize) -> bool {
+    let Some(engine) = start_call(engine) else {
+        return false;
+    };
+    engine.thread_count = if thread_count == 0 { available_threads() } else { thread_count };
+    return true;
+}
+
+/// Uploads `width * height` pixels, stored row by row from the top, as the diffuse texture of every
+/// material of the object at `object_index`. Null `pixels` removes the texture. Returns false if the
+/// object or size is invalid.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`, and `pixels` must be null or
+/// valid for reads of `width * height` pixels.
+#[no_mangle]
+pub unsafe extern "C" fn engine_set_object_texture(engine: EngineHandle, object_index: usize, pixels: *const Color,
+                                                   width: i32, height: i32, filter: Filter, wrap: Wrap) -> bool {
+    let Some(object) = start_call(engine).and_then(|engine| engine.scene.object_mut(object_index)) else {
+        return false;
+    };
+    if pixels.is_null() {
+        object.materials.iter_mut().for_each(|material| material.diffuse_texture = None);
+        return true;
+    }
+    if width <= 0 || height <= 0 {
+        return false;
+    }
+    let (width, height) = (width as usize, height as usize);
+    let pixels = std::slice::from_raw_parts(pixels, width * height);
+    let texture = Arc::new(Texture::new(width, height, pixels, filter, wrap));
+    object.materials.iter_mut().for_each(|material| material.diffuse_texture = Some(texture.clone()));
+    return true;
+}
+
+/// Loads the image file at `path` (PNG, BMP, PPM or TGA) as the diffuse texture of every material of
+/// the object at `object_index`. Returns false if the object is invalid or the file cannot be decoded,
+/// in which case `engine_last_error` describes why.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`, and `path` must be null or
+/// a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn engine_load_object_texture(engine: EngineHandle, object_index: usize, path: *const c_char,
+                                                    filter: Filter, wrap: Wrap) -> bool {
+    let Some(engine) = start_call(engine) else {
+        return false;
+    };
+    if path.is_null() || engine.scene.object_mut(object_index).is_none() {
+        return false;
+    }
+    let path = CStr::from_ptr(path).to_string_lossy();
+    let image = match load_image(&path) {
+        Ok(image) => image,
+        Err(error) => {
+            engine.set_error(error.to_string());
+            return false;
+        }
+    };
+    let texture = Arc::new(Texture::new(image.width, image.height, &image.pixels, filter, wrap));
+    for material in engine.scene.object_mut(object_index).unwrap().materials.iter_mut() {
+        material.diffuse_texture = Some(texture.clone());
+    }
+    return true;
+}
+
+/// Updates the scene and renders it into the host-owned `pixels`, which must hold `height` rows
+/// of `width` pixels each, with consecutive rows starting `stride` pixels apart.
+/// Returns false and leaves `pixels` untouched if any argument is invalid.
+///
+/// # Safety
+/// `engine` must be null or a live handle returned by `engine_create`, and `pixels` must be null or
+/// valid for writes of `stride * (height - 1) + width` pixels.
+#[no_mangle]
+pub unsafe extern "C" fn engine_update_and_render(engine: EngineHandle, user_input: UserInput, delta_time: f32,
+                                                  pixels: *mut Color, width: i32, height: i32, stride: i32) -> bool {
+    let Some(engine) = start_call(engine) else {
+        return false;
+    };
+    if pixels.is_null() || width <= 0 || height <= 0 || stride < width {
+        return false;
+    }
+    update_scene(&mut engine.scene, &user_input, delta_time);
+    let depth_buffer = engine.render(ScreenSize { width, height });
+
+    let length = stride as usize * (height - 1) as usize + width as usize;
+    let pixels = std::slice::from_raw_parts_mut(pixels, length);
+    depth_buffer.copy_to(pixels, stride as usize);
+    return true;
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const NO_INPUT: UserInput = UserInput {
+        w_pressed: false,
+        a_pressed: false,
+        s_pressed: false,
+        d_pressed: false,
+        q_pressed: false,
+        e_pressed: false,
+        shift_pressed: false,
+    };
+    const BLACK: Color = Color { red: 0, green: 0, blue: 0, alpha: 0 };
+
+    /// Renders a small frame with the engine's current settings.
+    unsafe fn render_frame(engine: EngineHandle) {
+        let mut pixels = vec![BLACK; 32 * 24];
+        assert!(engine_update_and_render(engine, NO_INPUT, 0.5, pixels.as_mut_ptr(), 32, 24, 32));
+    }
+
     #[test]
-    fn test() {
-        let input = UserInput {
-            w_pressed: false,
-            a_pressed: false,
-            s_pressed: false,
-            d_pressed: false,
-            q_pressed: false,
-            e_pressed: false,
-            shift_pressed: false,
-        };
-        create_scene();
-        for _ in 0..1 {
-            update_and_render(200, 100, input, 0.5);
+    fn engines_render_into_host_pixels() {
+        let mut first_pixels = vec![BLACK; 200 * 100];
+        let mut second_pixels = vec![BLACK; 80 * 48];
+        let first = engine_create();
+        let second = engine_create();
+        for _ in 0..2 {
+            unsafe {
+                assert!(engine_update_and_render(first, NO_INPUT, 0.5, first_pixels.as_mut_ptr(), 200, 100, 200));
+                assert!(engine_update_and_render(second, NO_INPUT, 0.5, second_pixels.as_mut_ptr(), 64, 48, 80));
+            }
+        }
+        assert_eq!(second_pixels[0].red, 200);
+        assert_eq!(second_pixels[70].red, 0);
+        unsafe {
+            assert!(!engine_update_and_render(first, NO_INPUT, 0.5, first_pixels.as_mut_ptr(), 200, 100, 100));
+        }
+        unsafe {
+            engine_destroy(first);
+            engine_destroy(second);
+        }
+    }
+
+    #[test]
+    fn last_error_describes_the_most_recent_call() {
+        let engine = engine_create();
+        unsafe {
+            let path = c"missing.png".as_ptr();
+            assert!(!engine_load_object_texture(engine, 0, path, Filter::Nearest, Wrap::Repeat));
+            assert!(!engine_last_error(engine).is_null());
+            assert!(engine_set_object_shading(engine, 0, Shading::Flat));
+            assert!(engine_last_error(engine).is_null());
+            engine_destroy(engine);
+        }
+    }
+
+    #[test]
+    fn object_shading_is_selected() {
+        let engine = engine_create();
+        unsafe {
+            assert!(engine_set_object_shading(engine, 0, Shading::Flat));
+            assert!(!engine_set_object_shading(engine, 1, Shading::Flat));
+            render_frame(engine);
+            engine_destroy(engine);
+        }
+    }
+
+    #[test]
+    fn object_texture_is_uploaded() {
+        let engine = engine_create();
+        let texture = [BLACK, Color { red: 255, green: 255, blue: 255, alpha: 0 }];
+        unsafe {
+            assert!(engine_set_object_texture(engine, 0, texture.as_ptr(), 2, 1, Filter::Trilinear, Wrap::Repeat));
+            assert!(!engine_set_object_texture(engine, 0, texture.as_ptr(), 0, 1, Filter::Trilinear, Wrap::Repeat));
+            assert!(!engine_set_object_texture(engine, 1, texture.as_ptr(), 2, 1, Filter::Trilinear, Wrap::Repeat));
+            render_frame(engine);
+            engine_destroy(engine);
+        }
+    }
+
+    #[test]
+    fn thread_count_is_set() {
+        let engine = engine_create();
+        unsafe {
+            assert!(engine_set_thread_count(engine, 1));
+            render_frame(engine);
+            assert!(engine_set_thread_count(engine, 0));
+            render_frame(engine);
+            assert!(!engine_set_thread_count(std::ptr::null_mut(), 1));
+            engine_destroy(engine);
+        }
+    }
+
+    #[test]
+    fn nodes_are_found_attached_and_removed() {
+        let engine = engine_create();
+        unsafe {
+            assert_eq!(engine_find_node(engine, c"camera".as_ptr()), 1);
+            assert_eq!(engine_find_node(engine, c"wheel".as_ptr()), -1);
+            assert!(!engine_set_node_parent(engine, 0, 0));
+            assert!(!engine_remove_node(engine, 1));
+            engine_destroy(engine);
+        }
+    }
+
+    #[test]
+    fn node_scale_rejects_zero_and_infinite_factors() {
+        let engine = engine_create();
+        unsafe {
+            assert!(engine_set_node_scale(engine, 0, 1.5, 0.5, -1.0));
+            assert!(!engine_set_node_scale(engine, 0, 1.0, 0.0, 1.0));
+            assert!(!engine_set_node_scale(engine, 0, 1.0, f32::INFINITY, 1.0));
+            render_frame(engine);
+            engine_destroy(engine);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn object_wireframe_is_toggled() {
+        let engine = engine_create();
+        unsafe {
+            assert!(engine_set_object_wireframe(engine, 0, false));
+            assert!(!engine_set_object_wireframe(engine, 1, false));
+            render_frame(engine);
+            engine_destroy(engine);
+        }
+    }
+
+    const LAMP: LightDesc = LightDesc {
+        light_type: LightType::Spot,
+        color: [1.0, 0.5, 0.2],
+        intensity: 2.0,
+        position: [0.0, 0.5, 0.0],
+        direction: [0.0, 0.0, 1.0],
+        attenuation: [1.0, 0.0, 0.5],
+        inner_angle: 10.0,
+        outer_angle: 20.0,
+    };
+
+    #[test]
+    fn lights_are_added_under_nodes() {
+        let engine = engine_create();
+        unsafe {
+            assert_eq!(engine_add_light(engine, &LAMP, 1), 3);
+            assert_eq!(engine_add_light(engine, &LightDesc { direction: [0.0; 3], ..LAMP }, 1), -1);
+            assert!(!engine_last_error(engine).is_null());
+            for attenuation in [[0.0; 3], [1.0, -0.5, 0.0], [1.0, 0.0, f32::INFINITY], [f32::NAN, 1.0, 0.0]] {
+                assert_eq!(engine_add_light(engine, &LightDesc { attenuation, ..LAMP }, 1), -1);
+                assert!(!engine_last_error(engine).is_null());
+            }
+            assert_eq!(engine_add_light(engine, std::ptr::null(), 1), -1);
+            assert!(!engine_last_error(engine).is_null());
+            assert_eq!(engine_add_light(engine, &LightDesc { attenuation: [0.0, 0.0, 1.0], ..LAMP }, -1), 4);
+            render_frame(engine);
+            engine_destroy(engine);
+        }
+    }
+
+    #[test]
+    fn light_shadow_settings_are_validated() {
+        let engine = engine_create();
+        unsafe {
+            assert_eq!(engine_add_light(engine, &LAMP, 1), 3);
+            assert!(engine_set_light_shadow(engine, 3, MAX_SHADOW_RESOLUTION, 0.01, 1));
+            assert!(engine_set_light_shadow(engine, 3, 64, 0.01, 1));
+            assert!(engine_set_light_shadow(engine, 2, 128, 0.01, 0));
+            assert!(!engine_set_light_shadow(engine, 0, 64, 0.01, 1));
+            assert!(!engine_set_light_shadow(engine, 3, MAX_SHADOW_RESOLUTION + 1, 0.01, 1));
+            assert!(!engine_set_light_shadow(engine, 3, 64, 0.01, i32::MAX));
+            assert!(!engine_set_light_shadow(engine, 3, 64, -0.01, 1));
+            render_frame(engine);
+            engine_destroy(engine);
+        }
+    }
+
+    #[test]
+    fn shadow_cascades_are_validated() {
+        let engine = engine_create();
+        unsafe {
+            assert_eq!(engine_add_light(engine, &LAMP, 1), 3);
+            assert!(engine_set_light_shadow(engine, 3, 64, 0.01, 1));
+            assert!(!engine_set_light_shadow_cascades(engine, 2, 2, false));
+            assert!(engine_set_light_shadow(engine, 2, 128, 0.01, 0));
+            assert!(engine_set_light_shadow_cascades(engine, 2, 3, true));
+            assert!(!engine_set_light_shadow_cascades(engine, 2, 0, false));
+            assert!(!engine_set_light_shadow_cascades(engine, 2, usize::MAX, false));
+            assert!(!engine_set_light_shadow_cascades(engine, 3, 2, false));
+            render_frame(engine);
+            engine_destroy(engine);
+        }
+    }
+}
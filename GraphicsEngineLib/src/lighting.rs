@@ -0,0 +1,426 @@
+use crate::game::{NodeId, Scene};
+use crate::math::{Mat4x4, Vec3, Vec4};
+use crate::render::{render_shadow_map, DepthBuffer};
+use crate::texture::Texture;
+use crate::Color;
+use std::sync::Arc;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shading {
+    /// One color per triangle, lit with the face normal at the triangle center.
+    Flat,
+    /// Color lit at every vertex and interpolated across the triangle.
+    Gouraud,
+    /// Normals and positions interpolated across the triangle and lit at every pixel.
+    Phong,
+}
+
+/// Surface reflectance used by the Blinn-Phong lighting model. Colors are RGB in 0..1, lit and
+/// written to the framebuffer as they are, like the sRGB values of textures.
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub ambient: Vec3,
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+    pub shininess: f32,
+    /// 1 for opaque surfaces, lower values blend the surface over what is behind it.
+    pub opacity: f32,
+    /// MTL illumination model: 0 shows the diffuse color unlit, 1 adds ambient and diffuse
+    /// lighting only, 2 and above also add the specular highlight.
+    pub illumination: u32,
+    /// Multiplies the ambient and diffuse colors when present.
+    pub diffuse_texture: Option<Arc<Texture>>,
+    /// Height map whose slope tilts the interpolated normal with Phong shading.
+    pub bump_texture: Option<Arc<Texture>>,
+    /// Scales the bump map heights, 1 treating a full 0..1 height step as one texel wide.
+    pub bump_multiplier: f32,
+}
+
+/// Light source placed by a scene node. Directional and spot lights shine along the +z axis of the node.
+#[derive(Clone, Copy, Debug)]
+pub enum Light {
+    /// Parallel rays of the same strength everywhere, like sunlight.
+    Directional { color: Vec3, intensity: f32 },
+    /// Shines in all directions from the node position.
+    Point { color: Vec3, intensity: f32, attenuation: Attenuation },
+    /// Point light limited to a cone, at full strength up to `inner_angle` degrees off its axis and
+    /// fading out towards `outer_angle` degrees.
+    Spot { color: Vec3, intensity: f32, attenuation: Attenuation, inner_angle: f32, outer_angle: f32 },
+}
+
+/// Light strength is divided by `constant + linear * d + quadratic * d^2` at distance d.
+#[derive(Clone, Copy, Debug)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+/// How a directional or spot light casts shadows, which are looked up in a depth map rendered from
+/// the light every frame.
+#[derive(Clone, Copy, Debug)]
+pub struct Shadow {
+    /// Width and height of the depth map in texels.
+    pub resolution: i32,
+    /// World-space distance a shaded point is moved along its normal before the lookup, so that lit
+    /// surfaces do not shadow themselves. Filtering tests texels further from the point, which needs
+    /// a larger bias on surfaces at an angle to the light.
+    pub bias: f32,
+    /// Texels on each side of the looked up one whose depth tests are averaged (percentage-closer
+    /// filtering), 0 for hard shadow edges.
+    pub pcf_radius: i32,
+    /// Directional lights split the camera's view into this many slices by distance, each with its
+    /// own map, so that near shadows stay sharp when the view reaches far. With one cascade a single
+    /// map covers all objects. `bias` applies to the first cascade and grows with the texel size of
+    /// the others.
+    pub cascades: usize,
+    /// Tints the lit surfaces by the index of the cascade they look up.
+    pub debug_cascades: bool,
+}
+
+/// Largest `Shadow::resolution`, at which the depth map of every cascade already takes 128 MB.
+pub const MAX_SHADOW_RESOLUTION: i32 = 4096;
+
+/// Largest `Shadow::pcf_radius`, which already averages over 33x33 texels per shaded point.
+pub const MAX_PCF_RADIUS: i32 = 16;
+
+/// Largest `Shadow::cascades`, one for each tint of `debug_cascades`. Every cascade renders a full map
+/// each frame.
+pub const MAX_SHADOW_CASCADES: usize = CASCADE_TINTS.len();
+
+/// Depth of the surfaces closest to a light, as seen from the light.
+pub struct ShadowMap {
+    pub shadow: Shadow,
+    /// From near to far, each covering the points up to its `z_far` from the camera.
+    pub cascades: Vec<ShadowCascade>,
+    /// View matrix of the camera, giving the distance that selects the cascade in z.
+    pub camera_view_mat: Mat4x4,
+}
+
+/// One map of a shadow, fitted around a slice of the camera's view or around all objects.
+pub struct ShadowCascade {
+    /// Projects world positions to the normalized device coordinates of `depth`.
+    pub clip_mat: Mat4x4,
+    pub depth: DepthBuffer,
+    /// Camera distances up to which points use this cascade, blending towards the next one from
+    /// `blend_start` on.
+    pub blend_start: f32,
+    pub z_far: f32,
+    /// Bias of the shadow scaled to the texel size of this cascade.
+    pub bias: f32,
+}
+
+/// Light together with where its node is in the world this frame.
+pub struct PlacedLight {
+    pub node: NodeId,
+    pub light: Light,
+    pub position: Vec4,
+    /// Unit vector the light shines along.
+    pub direction: Vec4,
+    /// None for lights that do not cast shadows.
+    pub shadow_map: Option<ShadowMap>,
+}
+
+/// Lights and viewer shared by every point shaded in a frame.
+pub struct SceneLighting {
+    pub lights: Vec<PlacedLight>,
+    pub ambient_color: Vec3,
+    pub camera_position: Vec4,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            ambient: Vec3::new(0.08, 0.08, 0.0),
+            diffuse: Vec3::new(0.78, 0.78, 0.0),
+            specular: Vec3::new(0.5, 0.5, 0.5),
+            shininess: 32.0,
+            opacity: 1.0,
+            illumination: 2,
+            diffuse_texture: None,
+            bump_texture: None,
+            bump_multiplier: 1.0,
+        }
+    }
+}
+
+impl Attenuation {
+    /// Whether the strength stays finite and positive at every distance, which needs finite factors
+    /// that are not negative and not all zero.
+    pub fn is_valid(&self) -> bool {
+        let factors = [self.constant, self.linear, self.quadratic];
+        return factors.iter().all(|factor| factor.is_finite() && *factor >= 0.0)
+            && factors.iter().any(|factor| *factor > 0.0);
+    }
+
+    fn factor(&self, distance: f32) -> f32 {
+        return 1.0 / (self.constant + self.linear * distance + self.quadratic * distance * distance);
+    }
+}
+
+impl PlacedLight {
+    /// Unit vector from `position` towards the light and the light color arriving there.
+    fn incoming(&self, position: &Vec4) -> (Vec4, Vec3) {
+        let (color, intensity, attenuation) = match self.light {
+            Light::Directional { color, intensity } => {
+                let mut to_light = &self.direction * -1.0;
+                to_light.w = 0.0;
+                return (to_light, &color * intensity);
+            }
+            Light::Point { color, intensity, attenuation } |
+            Light::Spot { color, intensity, attenuation, .. } => (color, intensity, attenuation),
+        };
+        let offset = &self.position - position;
+        let mut to_light = offset.normalized();
+        to_light.w = 0.0;
+        let mut strength = intensity * attenuation.factor(offset.len());
+        if let Light::Spot { inner_angle, outer_angle, .. } = self.light {
+            let cos_angle = -to_light.dot(&self.direction);
+            let (cos_inner, cos_outer) = (inner_angle.to_radians().cos(), outer_angle.to_radians().cos());
+            let t = ((cos_angle - cos_outer) / (cos_inner - cos_outer).max(1e-6)).clamp(0.0, 1.0);
+            strength *= t * t * (3.0 - 2.0 * t);
+        }
+        return (to_light, &color * strength);
+    }
+}
+
+/// Multiplies the colors of the surfaces using each cascade when debugging cascades.
+const CASCADE_TINTS: [Vec3; 4] = [
+    Vec3::new(1.0, 0.3, 0.3),
+    Vec3::new(0.3, 1.0, 0.3),
+    Vec3::new(0.3, 0.3, 1.0),
+    Vec3::new(1.0, 1.0, 0.3),
+];
+
+impl ShadowMap {
+    /// Index of the cascade looked up at `position`, None beyond the last one.
+    fn cascade_at(&self, position: &Vec4) -> Option<usize> {
+        return self.cascade_at_distance((&self.camera_view_mat * position).z);
+    }
+
+    fn cascade_at_distance(&self, camera_distance: f32) -> Option<usize> {
+        return self.cascades.iter().position(|cascade| camera_distance <= cascade.z_far);
+    }
+
+    /// Fraction of the light reaching `position` past the shadow casters, from 0 in full shadow to 1
+    /// when lit, blended between neighbouring cascades. Points beyond the last cascade are lit.
+    fn visibility(&self, position: &Vec4, normal: &Vec4) -> f32 {
+        let camera_distance = (&self.camera_view_mat * position).z;
+        let Some(index) = self.cascade_at_distance(camera_distance) else {
+            return 1.0;
+        };
+        let cascade = &self.cascades[index];
+        let visibility = cascade.visibility(&self.shadow, position, normal);
+        let Some(next) = self.cascades.get(index + 1).filter(|_| camera_distance > cascade.blend_start) else {
+            return visibility;
+        };
+        let t = (camera_distance - cascade.blend_start) / (cascade.z_far - cascade.blend_start);
+        return visibility + (next.visibility(&self.shadow, position, normal) - visibility) * t;
+    }
+}
+
+impl ShadowCascade {
+    /// Fraction of the depth tests around the texel of `position` that find no surface between it and
+    /// the light.
+    fn visibility(&self, shadow: &Shadow, position: &Vec4, normal: &Vec4) -> f32 {
+        let mut projected = &self.clip_mat * &(position + &(normal * self.bias));
+        if projected.w <= 0.0 {
+            return 1.0;
+        }
+        projected.perspective_div();
+        let size = self.depth.screen_size();
+        let x = ((projected.x + 1.0) / 2.0 * size.width as f32).floor() as i32;
+        let y = ((projected.y + 1.0) / 2.0 * size.height as f32).floor() as i32;
+        let radius = shadow.pcf_radius.max(0);
+        let mut lit = 0;
+        for texel_y in y - radius..=y + radius {
+            for texel_x in x - radius..=x + radius {
+                if projected.z <= self.depth.depth_at(texel_x, texel_y) {
+                    lit += 1;
+                }
+            }
+        }
+        return lit as f32 / ((2 * radius + 1) * (2 * radius + 1)) as f32;
+    }
+}
+
+impl SceneLighting {
+    /// Places the lights of all scene nodes using the world matrices from `Scene::world_matrices`,
+    /// and renders the shadow maps of the ones casting shadows for a screen of `aspect_ratio`. The maps
+    /// are drawn into the depth buffers of the same lights in `previous` where their sizes match.
+    pub fn new(scene: &Scene, world_mats: &[Mat4x4], aspect_ratio: f32, previous: Option<SceneLighting>) -> Self {
+        let mut previous_maps: Vec<(NodeId, ShadowMap)> = previous.map_or(vec![], |lighting| {
+            lighting.lights.into_iter().filter_map(|light| Some((light.node, light.shadow_map?))).collect()
+        });
+        let lights = scene.lights().map(|(node, light)| {
+            let world_mat = &world_mats[node];
+            let mut direction = (world_mat * &Vec4::new(0.0, 0.0, 1.0, 0.0)).normalized();
+            direction.w = 0.0;
+            let previous_map = previous_maps.iter().position(|(previous_node, _)| *previous_node == node)
+                .map(|index| previous_maps.swap_remove(index).1);
+            let shadow_map = scene.node(node).unwrap().shadow.and_then(|shadow| {
+                render_shadow_map(scene, world_mats, aspect_ratio, world_mat, light, shadow, previous_map)
+            });
+            let position = world_mat * &Vec4::new3d(0.0, 0.0, 0.0);
+            PlacedLight { node, light: *light, position, direction, shadow_map }
+        }).collect();
+        let camera_position = &world_mats[scene.camera_node] * &Vec4::new3d(0.0, 0.0, 0.0);
+        return Self { lights, ambient_color: scene.ambient_color, camera_position };
+    }
+
+    /// Evaluates the ambient term and the sum of the diffuse and Blinn-Phong specular terms of all
+    /// lights at `position` with unit `normal`, where `texture_color` is the diffuse texture sampled
+    /// at that point.
+    pub fn shade(&self, material: &Material, position: &Vec4, normal: &Vec4, texture_color: &Vec3) -> Vec3 {
+        if material.illumination == 0 {
+            return material.diffuse.mul_elements(texture_color);
+        }
+        let mut color = material.ambient.mul_elements(texture_color).mul_elements(&self.ambient_color);
+        let diffuse_color = material.diffuse.mul_elements(texture_color);
+        let to_camera = (&self.camera_position - position).normalized();
+        for light in &self.lights {
+            let (to_light, mut light_color) = light.incoming(position);
+            let diffuse_factor = normal.dot(&to_light);
+            if diffuse_factor <= 0.0 {
+                continue;
+            }
+            if let Some(shadow_map) = &light.shadow_map {
+                light_color = &light_color * shadow_map.visibility(position, normal);
+            }
+            color = &color + &(&diffuse_color.mul_elements(&light_color) * diffuse_factor);
+            if material.illumination == 1 {
+                continue;
+            }
+            let half_vector = (&to_camera + &to_light).normalized();
+            let specular_factor = normal.dot(&half_vector).max(0.0).powf(material.shininess);
+            color = &color + &(&material.specular.mul_elements(&light_color) * specular_factor);
+        }
+        let debugged_cascade = self.lights.iter()
+            .filter_map(|light| light.shadow_map.as_ref().filter(|shadow_map| shadow_map.shadow.debug_cascades))
+            .find_map(|shadow_map| shadow_map.cascade_at(position));
+        if let Some(index) = debugged_cascade {
+            color = color.mul_elements(&CASCADE_TINTS[index]);
+        }
+        return color;
+    }
+}
+
+pub fn to_color(rgb: &Vec3) -> Color {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    return Color {
+        red: channel(rgb.x),
+        green: channel(rgb.y),
+        blue: channel(rgb.z),
+        alpha: 0,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placed(light: Light, position: Vec4, direction: Vec4) -> PlacedLight {
+        return PlacedLight { node: 0, light, position, direction, shadow_map: None };
+    }
+
+    fn assert_near(actual: Vec3, expected: Vec3) {
+        let distance = (&actual - &expected).len();
+        assert!(distance < 1e-5, "{:?} is not {:?}", actual, expected);
+    }
+
+    #[test]
+    fn light_contributions_add_up() {
+        let material = Material {
+            ambient: Vec3::new(0.1, 0.1, 0.1),
+            diffuse: Vec3::new(1.0, 0.5, 0.25),
+            illumination: 1,
+            ..Material::default()
+        };
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let (position, normal) = (Vec4::new3d(0.0, 0.0, 0.0), Vec4::new(0.0, 1.0, 0.0, 0.0));
+        let lighting = |lights: Vec<PlacedLight>| {
+            let lighting = SceneLighting { lights, ambient_color: white, camera_position: Vec4::new3d(0.0, 1.0, -1.0) };
+            return lighting.shade(&material, &position, &normal, &white);
+        };
+        let down = Vec4::new(0.0, -1.0, 0.0, 0.0);
+        let red = Light::Directional { color: Vec3::new(1.0, 0.0, 0.0), intensity: 0.5 };
+        let red_sun = || placed(red, Vec4::default(), down.clone());
+        let blue = Light::Directional { color: Vec3::new(0.0, 0.0, 1.0), intensity: 2.0 };
+        let blue_sun = || placed(blue, Vec4::default(), Vec4::new(0.6, -0.8, 0.0, 0.0));
+        assert_near(lighting(vec![]), Vec3::new(0.1, 0.1, 0.1));
+        assert_near(lighting(vec![red_sun()]), Vec3::new(0.6, 0.1, 0.1));
+        assert_near(lighting(vec![red_sun(), blue_sun()]), Vec3::new(0.6, 0.1, 0.1 + 0.25 * 2.0 * 0.8));
+
+        // point light two units above with the strength divided by 1 + 0.5 * 2^2
+        let attenuation = Attenuation { constant: 1.0, linear: 0.0, quadratic: 0.5 };
+        let bulb = Light::Point { color: white, intensity: 3.0, attenuation };
+        assert_near(lighting(vec![placed(bulb, Vec4::new3d(0.0, 2.0, 0.0), down.clone())]), Vec3::new(1.1, 0.6, 0.35));
+        // light from below does not reach the surface
+        assert_near(lighting(vec![placed(bulb, Vec4::new3d(0.0, -2.0, 0.0), down.clone())]), Vec3::new(0.1, 0.1, 0.1));
+    }
+
+    #[test]
+    fn blinn_phong_adds_ambient_diffuse_and_specular() {
+        let material = Material {
+            ambient: Vec3::new(0.2, 0.1, 0.0),
+            diffuse: Vec3::new(0.5, 0.5, 1.0),
+            specular: Vec3::new(1.0, 1.0, 0.5),
+            shininess: 8.0,
+            ..Material::default()
+        };
+        // light straight down on an upward surface, seen from 45 degrees so that the half vector is
+        // 22.5 degrees off the normal
+        let sun = Light::Directional { color: Vec3::new(1.0, 1.0, 1.0), intensity: 0.8 };
+        let lighting = SceneLighting {
+            lights: vec![placed(sun, Vec4::default(), Vec4::new(0.0, -1.0, 0.0, 0.0))],
+            ambient_color: Vec3::new(0.5, 0.5, 0.5),
+            camera_position: Vec4::new3d(2.0, 2.0, 0.0),
+        };
+        let shade = |material: &Material| {
+            let (position, normal) = (Vec4::new3d(0.0, 0.0, 0.0), Vec4::new(0.0, 1.0, 0.0, 0.0));
+            return lighting.shade(material, &position, &normal, &Vec3::new(1.0, 1.0, 1.0));
+        };
+        let specular = 22.5_f32.to_radians().cos().powf(8.0) * 0.8;
+        let expected = |channel: usize| {
+            let ambient = [0.2, 0.1, 0.0][channel] * 0.5;
+            return ambient + [0.5, 0.5, 1.0][channel] * 0.8 + [1.0, 1.0, 0.5][channel] * specular;
+        };
+        assert_near(shade(&material), Vec3::new(expected(0), expected(1), expected(2)));
+
+        // a higher shininess narrows the highlight, which then falls off faster at the same angle
+        let shiny = shade(&Material { shininess: 64.0, ..material.clone() });
+        assert!(shiny.x < shade(&material).x - 0.3, "{:?}", shiny);
+    }
+
+    #[test]
+    fn spot_light_fades_between_its_cones() {
+        let material = Material {
+            ambient: Vec3::default(),
+            diffuse: Vec3::new(1.0, 1.0, 1.0),
+            illumination: 1,
+            ..Material::default()
+        };
+        let spot = Light::Spot {
+            color: Vec3::new(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            attenuation: Attenuation { constant: 1.0, linear: 0.0, quadratic: 0.0 },
+            inner_angle: 20.0,
+            outer_angle: 40.0,
+        };
+        let normal = Vec4::new(0.0, 1.0, 0.0, 0.0);
+        // the spot points straight down from one unit above, and `x` units off its axis are at atan(x)
+        let brightness = |x: f32| {
+            let light = placed(spot, Vec4::new3d(0.0, 1.0, 0.0), Vec4::new(0.0, -1.0, 0.0, 0.0));
+            let lighting = SceneLighting { lights: vec![light], ambient_color: Vec3::default(), camera_position: Vec4::default() };
+            let white = Vec3::new(1.0, 1.0, 1.0);
+            return lighting.shade(&material, &Vec4::new3d(x, 0.0, 0.0), &normal, &white).x;
+        };
+        let cos_angle = |x: f32| 1.0 / (1.0 + x * x).sqrt();
+        assert!((brightness(0.0) - 1.0).abs() < 1e-6);
+        assert!((brightness(0.3) - cos_angle(0.3)).abs() < 1e-6);
+        let halfway = brightness(30.0_f32.to_radians().tan());
+        assert!(halfway > 0.1 && halfway < cos_angle(0.6), "{}", halfway);
+        assert_eq!(brightness(0.9), 0.0);
+    }
+}
@@ -1,21 +1,26 @@
-use std::mem::swap;
-use std::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub};
+use std::collections::HashMap;
+use std::ops::{Add, AddAssign, Mul, MulAssign, Neg, Range, Sub};
 use crate::Color;
 
-#[derive(Debug)]
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd;
+
+#[derive(Debug, Clone, Copy)]
 pub struct Vec2<T> {
     pub x: T,
     pub y: T,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
     pub z: f32,
 }
 
+/// `repr(C)` so that the SIMD code can load the components as one array.
 #[derive(Debug, Clone)]
+#[repr(C)]
 pub struct Vec4 {
     pub x: f32,
     pub y: f32,
@@ -31,22 +36,52 @@ pub struct Mat4x4 {
     pub content: [[f32; 4]; 4],
 }
 
-#[derive(Clone)]
+/// Rotation as a unit quaternion `w + xi + yj + zk`.
+#[derive(Debug, Clone, Copy)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+/// Placement of an object in its parent space: scaled first, then rotated, then translated.
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+#[derive(Debug, Clone)]
 pub struct Triangle {
     pub p1: Vec4,
     pub p2: Vec4,
     pub p3: Vec4,
-    pub world_normal: Option<Vec4>,
 }
 
-pub trait Plane {
-    fn new_plane(point: Vec4, normal: Vec4) -> Self;
-    fn is_point_inside(&self, point: &Vec4) -> bool;
-    fn intersect_with_segment(&self, p1: &Vec4, p2: &Vec4) -> Vec4;
+#[derive(Debug, Clone)]
+pub struct Vertex {
+    pub position: Vec4,
+    pub normal: Option<Vec4>,
+    pub uv: Option<Vec2<f32>>,
 }
 
+/// Triangle mesh where every three consecutive `indices` reference one triangle in `vertices`,
+/// so vertices shared between triangles are stored and transformed only once.
+#[derive(Debug)]
 pub struct Mesh {
-    pub triangles: Vec<Triangle>,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    /// Consecutive ranges of `indices` covering all of them, each drawn with its own material.
+    pub submeshes: Vec<Submesh>,
+}
+
+/// Range of `Mesh::indices` drawn with the material at index `material` of the owning object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Submesh {
+    pub indices: Range<usize>,
+    pub material: usize,
 }
 
 pub trait Lerp<T> {
@@ -59,9 +94,32 @@ impl Lerp<f32> for f32 {
     }
 }
 
+impl Lerp<Vec2<f32>> for Vec2<f32> {
+    fn lerp(&self, rhs: &Vec2<f32>, alpha: f32) -> Vec2<f32> {
+        return Vec2::new(self.x.lerp(&rhs.x, alpha), self.y.lerp(&rhs.y, alpha));
+    }
+}
+
+impl Lerp<Vec3> for Vec3 {
+    fn lerp(&self, rhs: &Vec3, alpha: f32) -> Vec3 {
+        return Vec3::new(self.x.lerp(&rhs.x, alpha),
+                         self.y.lerp(&rhs.y, alpha),
+                         self.z.lerp(&rhs.z, alpha));
+    }
+}
+
+impl Lerp<Vec4> for Vec4 {
+    fn lerp(&self, rhs: &Vec4, alpha: f32) -> Vec4 {
+        return Vec4::new(self.x.lerp(&rhs.x, alpha),
+                         self.y.lerp(&rhs.y, alpha),
+                         self.z.lerp(&rhs.z, alpha),
+                         self.w.lerp(&rhs.w, alpha));
+    }
+}
+
 impl Lerp<Color> for Color {
     fn lerp(&self, rhs: &Color, mut alpha: f32) -> Color {
-        if alpha < 0.0 || alpha > 1.0 {
+        if !(0.0..=1.0).contains(&alpha) {
             alpha = alpha.clamp(0.0, 1.0);
         }
 
@@ -93,6 +151,118 @@ impl Vec3 {
         let self_len = self.len();
         return Vec3::new(self.x / self_len, self.y / self_len, self.z / self_len);
     }
+
+    pub fn dot(&self, rhs: &Vec3) -> f32 {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    pub fn cross(&self, rhs: &Vec3) -> Vec3 {
+        Vec3::new(self.y * rhs.z - self.z * rhs.y,
+                  self.z * rhs.x - self.x * rhs.z,
+                  self.x * rhs.y - self.y * rhs.x)
+    }
+
+    /// Component-wise product, used to modulate one color by another.
+    pub fn mul_elements(&self, rhs: &Vec3) -> Vec3 {
+        Vec3::new(self.x * rhs.x, self.y * rhs.y, self.z * rhs.z)
+    }
+}
+
+impl Vertex {
+    pub const fn new(position: Vec4) -> Self {
+        Self { position, normal: None, uv: None }
+    }
+}
+
+impl Mesh {
+    /// Creates a mesh drawn entirely with the first material.
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        let submeshes = vec![Submesh { indices: 0..indices.len(), material: 0 }];
+        Self { vertices, indices, submeshes }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        self.indices.chunks_exact(3).map(|tr| [tr[0] as usize, tr[1] as usize, tr[2] as usize])
+    }
+
+    pub fn submesh_triangles(&self, submesh: &Submesh) -> impl Iterator<Item = [usize; 3]> + '_ {
+        self.indices[submesh.indices.clone()].chunks_exact(3)
+            .map(|tr| [tr[0] as usize, tr[1] as usize, tr[2] as usize])
+    }
+
+    /// Fills in the normals of vertices that have none by averaging the normals of the faces around
+    /// the vertex position. Faces meeting at more than `crease_angle` degrees keep a hard edge,
+    /// which splits the vertex into one copy per smoothing group. The triangle order, and so the
+    /// submeshes, stay the same.
+    pub fn generate_normals(&mut self, crease_angle: f32) {
+        let position_key = |v: &Vertex| (v.position.x.to_bits(), v.position.y.to_bits(), v.position.z.to_bits());
+
+        let face_normals: Vec<Vec4> = self.triangles().map(|[i1, i2, i3]| {
+            let (p1, p2, p3) = (&self.vertices[i1].position, &self.vertices[i2].position, &self.vertices[i3].position);
+            (p2 - p1).cross(&(p3 - p1))
+        }).collect();
+        let mut faces_around_position: HashMap<(u32, u32, u32), Vec<usize>> = HashMap::new();
+        for (face, triangle) in self.triangles().enumerate() {
+            for i in triangle {
+                faces_around_position.entry(position_key(&self.vertices[i])).or_default().push(face);
+            }
+        }
+
+        let min_cos = crease_angle.to_radians().cos();
+        let mut generated: HashMap<(usize, (u32, u32, u32)), u32> = HashMap::new();
+        let mut indices = Vec::with_capacity(self.indices.len());
+        for (face, triangle) in self.triangles().collect::<Vec<[usize; 3]>>().into_iter().enumerate() {
+            for i in triangle {
+                if self.vertices[i].normal.is_some() {
+                    indices.push(i as u32);
+                    continue;
+                }
+                // face normals are not normalized, so bigger faces contribute more
+                let face_normal = &face_normals[face];
+                let mut normal = Vec4::new(0.0, 0.0, 0.0, 0.0);
+                for &other in &faces_around_position[&position_key(&self.vertices[i])] {
+                    let other_normal = &face_normals[other];
+                    let cos = face_normal.dot(other_normal) / (face_normal.len() * other_normal.len());
+                    if other == face || cos >= min_cos {
+                        normal = &normal + other_normal;
+                    }
+                }
+                if normal.len() > 0.0 {
+                    normal = normal.normalized();
+                    normal.w = 0.0;
+                }
+
+                let key = (i, (normal.x.to_bits(), normal.y.to_bits(), normal.z.to_bits()));
+                let index = *generated.entry(key).or_insert_with(|| {
+                    let mut vertex = self.vertices[i].clone();
+                    vertex.normal = Some(normal);
+                    self.vertices.push(vertex);
+                    (self.vertices.len() - 1) as u32
+                });
+                indices.push(index);
+            }
+        }
+        self.indices = indices;
+        self.remove_unused_vertices();
+    }
+
+    fn remove_unused_vertices(&mut self) {
+        let mut remap = vec![u32::MAX; self.vertices.len()];
+        let mut vertices = vec![];
+        for index in self.indices.iter_mut() {
+            let old = *index as usize;
+            if remap[old] == u32::MAX {
+                remap[old] = vertices.len() as u32;
+                vertices.push(self.vertices[old].clone());
+            }
+            *index = remap[old];
+        }
+        self.vertices = vertices;
+    }
 }
 
 impl Vec4 {
@@ -104,16 +274,20 @@ impl Vec4 {
         Vec4 { x, y, z, w: 1.0 }
     }
 
+    /// Divides x, y and z by w and replaces w with 1/w, which is what perspective-correct
+    /// interpolation needs from every projected vertex.
     pub fn perspective_div(&mut self) {
-        if self.w != 0.0 {
-            self.x /= self.w;
-            self.y /= self.w;
-            self.z /= self.w;
-        }
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        simd::vec_perspective_div(self);
+        #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+        vec_perspective_div_scalar(self);
     }
 
     pub fn dot(&self, rhs: &Vec4) -> f32 {
-        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        return simd::vec_dot(self, rhs);
+        #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+        return vec_dot_scalar(self, rhs);
     }
 
     pub fn len(&self) -> f32 {
@@ -121,16 +295,17 @@ impl Vec4 {
     }
 
     pub fn normalized(&self) -> Vec4 {
-        let self_len = self.len();
-        return Vec4::new3d(self.x / self_len, self.y / self_len, self.z / self_len);
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        return simd::vec_normalized(self);
+        #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+        return vec_normalized_scalar(self);
     }
 
-    // v×w=(v2w3−v3w2)i+(v3w1−v1w3)j+(v1w2−v2w1)k
-    // (a2b3−a3b2)i−(a1b3−a3b1)j+(a1b2−a2b1)k
     pub fn cross(&self, rhs: &Vec4) -> Vec4 {
-        Vec4::new3d(self.y * rhs.z - self.z * rhs.y,
-                    self.z * rhs.x - self.x * rhs.z,
-                    self.x * rhs.y - self.y * rhs.x)
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        return simd::vec_cross(self, rhs);
+        #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+        return vec_cross_scalar(self, rhs);
     }
 
     pub fn cross_len_2d(&self, rhs: &Vec4) -> f32 {
@@ -142,33 +317,152 @@ impl Vec4 {
     }
 }
 
-impl Mat3x3 {
-    pub fn rotation(rotation: &Vec3) -> Mat3x3 {
-        let (x, y, z) = (rotation.x.to_radians(), rotation.y.to_radians(), rotation.z.to_radians());
-        let mut res = Mat3x3::default();
+impl Quat {
+    pub const IDENTITY: Quat = Quat { x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
 
-        res.content[0][0] = y.cos() * z.cos();
-        res.content[0][1] = x.sin() * y.sin() * z.cos() - x.cos() * z.sin();
-        res.content[0][2] = x.cos() * y.sin() * z.cos() + x.sin() * z.sin();
-        res.content[1][0] = y.cos() * z.sin();
-        res.content[1][1] = x.sin() * y.sin() * z.sin() + x.cos() * z.cos();
-        res.content[1][2] = x.cos() * y.sin() * z.sin() - x.sin() * z.cos();
-        res.content[2][0] = -y.sin();
-        res.content[2][1] = x.sin() * y.cos();
-        res.content[2][2] = x.cos() * y.cos();
+    /// Rotation by `angle` degrees around `axis`, counter-clockwise when looking against the axis.
+    pub fn from_axis_angle(axis: &Vec3, angle: f32) -> Quat {
+        let half_angle = angle.to_radians() / 2.0;
+        let axis = &axis.normalized() * half_angle.sin();
+        return Quat { x: axis.x, y: axis.y, z: axis.z, w: half_angle.cos() };
+    }
+
+    /// Same rotation as `Mat4x4::rotation` with Euler angles in degrees, which rotates around x,
+    /// then y, then z.
+    pub fn from_euler(rotation: &Vec3) -> Quat {
+        let x = Quat::from_axis_angle(&Vec3::new(1.0, 0.0, 0.0), rotation.x);
+        let y = Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), rotation.y);
+        let z = Quat::from_axis_angle(&Vec3::new(0.0, 0.0, 1.0), rotation.z);
+        return &(&z * &y) * &x;
+    }
+
+    /// Rotation that turns +z towards `forward` and +y as close to `up` as possible.
+    pub fn look_rotation(forward: &Vec3, up: &Vec3) -> Quat {
+        let forward = forward.normalized();
+        let right = up.cross(&forward).normalized();
+        let up = forward.cross(&right);
+        // the basis vectors are the columns of the rotation matrix
+        let (m00, m01, m02) = (right.x, up.x, forward.x);
+        let (m10, m11, m12) = (right.y, up.y, forward.y);
+        let (m20, m21, m22) = (right.z, up.z, forward.z);
+        let trace = m00 + m11 + m22;
+        let res = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quat { x: (m21 - m12) / s, y: (m02 - m20) / s, z: (m10 - m01) / s, w: s / 4.0 }
+        } else if m00 > m11 && m00 > m22 {
+            let s = (1.0 + m00 - m11 - m22).sqrt() * 2.0;
+            Quat { x: s / 4.0, y: (m01 + m10) / s, z: (m02 + m20) / s, w: (m21 - m12) / s }
+        } else if m11 > m22 {
+            let s = (1.0 + m11 - m00 - m22).sqrt() * 2.0;
+            Quat { x: (m01 + m10) / s, y: s / 4.0, z: (m12 + m21) / s, w: (m02 - m20) / s }
+        } else {
+            let s = (1.0 + m22 - m00 - m11).sqrt() * 2.0;
+            Quat { x: (m02 + m20) / s, y: (m12 + m21) / s, z: s / 4.0, w: (m10 - m01) / s }
+        };
+        return res.normalized();
+    }
 
+    pub fn dot(&self, rhs: &Quat) -> f32 {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z + self.w * rhs.w
+    }
+
+    pub fn normalized(&self) -> Quat {
+        let len = self.dot(self).sqrt();
+        return Quat { x: self.x / len, y: self.y / len, z: self.z / len, w: self.w / len };
+    }
+
+    /// Opposite rotation, which for a unit quaternion is its conjugate.
+    pub fn inverse(&self) -> Quat {
+        Quat { x: -self.x, y: -self.y, z: -self.z, w: self.w }
+    }
+
+    /// Interpolates along the shorter arc between the rotations at a constant angular speed.
+    pub fn slerp(&self, rhs: &Quat, alpha: f32) -> Quat {
+        let mut cos_angle = self.dot(rhs);
+        // q and -q are the same rotation, and the one closer to `self` takes the shorter arc
+        let rhs = if cos_angle < 0.0 {
+            cos_angle = -cos_angle;
+            Quat { x: -rhs.x, y: -rhs.y, z: -rhs.z, w: -rhs.w }
+        } else {
+            *rhs
+        };
+        let (self_weight, rhs_weight) = if cos_angle > 0.9995 {
+            // nearly the same rotation, where the sine below would lose all precision
+            (1.0 - alpha, alpha)
+        } else {
+            let angle = cos_angle.acos();
+            let sin_angle = angle.sin();
+            (((1.0 - alpha) * angle).sin() / sin_angle, (alpha * angle).sin() / sin_angle)
+        };
+        return Quat {
+            x: self.x * self_weight + rhs.x * rhs_weight,
+            y: self.y * self_weight + rhs.y * rhs_weight,
+            z: self.z * self_weight + rhs.z * rhs_weight,
+            w: self.w * self_weight + rhs.w * rhs_weight,
+        }.normalized();
+    }
+
+    pub fn to_mat3(self) -> Mat3x3 {
+        let Quat { x, y, z, w } = self;
+        return Mat3x3 {
+            content: [
+                [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w)],
+                [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w)],
+                [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y)],
+            ],
+        };
+    }
+
+    pub fn to_mat4(self) -> Mat4x4 {
+        let mut res = Mat4x4::default();
+        for (res_row, row) in res.content.iter_mut().zip(self.to_mat3().content) {
+            res_row[..3].copy_from_slice(&row);
+        }
+        res.content[3][3] = 1.0;
         return res;
     }
 }
 
+impl Transform {
+    pub fn from_translation(translation: Vec3) -> Transform {
+        Transform { translation, ..Transform::default() }
+    }
+
+    /// Matrix from the local space into the parent space.
+    pub fn matrix(&self) -> Mat4x4 {
+        let mut scale = Mat4x4::identity();
+        scale.content[0][0] = self.scale.x;
+        scale.content[1][1] = self.scale.y;
+        scale.content[2][2] = self.scale.z;
+        return &(&Mat4x4::translation(&self.translation) * &self.rotation.to_mat4()) * &scale;
+    }
+
+    /// Rotates by `rotation` around the parent space axes, after the current rotation.
+    pub fn rotate(&mut self, rotation: &Quat) {
+        self.rotation = (rotation * &self.rotation).normalized();
+    }
+}
+
 impl Mat4x4 {
-    pub fn translation(offset: &Vec3) -> Mat4x4 {
+    /// Multiplies every point by the matrix in place, which vectorizes better than multiplying the
+    /// points one by one when the `simd` feature is enabled.
+    pub fn transform_all(&self, points: &mut [Vec4]) {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        simd::transform_all(self, points);
+        #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+        points.iter_mut().for_each(|point| *point = mat_mul_vec_scalar(self, point));
+    }
+
+    pub fn identity() -> Mat4x4 {
         let mut res = Mat4x4::default();
-        res.content[0][0] = 1.0;
-        res.content[1][1] = 1.0;
-        res.content[2][2] = 1.0;
-        res.content[3][3] = 1.0;
+        for i in 0..4 {
+            res.content[i][i] = 1.0;
+        }
+        return res;
+    }
 
+    pub fn translation(offset: &Vec3) -> Mat4x4 {
+        let mut res = Mat4x4::identity();
         res.content[0][3] = offset.x;
         res.content[1][3] = offset.y;
         res.content[2][3] = offset.z;
@@ -176,6 +470,135 @@ impl Mat4x4 {
         return res;
     }
 
+    /// View matrix of a camera at `eye` looking at `target`, which puts the camera at the origin
+    /// looking along +z with `up` projected to +y.
+    pub fn look_at(eye: &Vec3, target: &Vec3, up: &Vec3) -> Mat4x4 {
+        let forward = (target - eye).normalized();
+        let right = up.cross(&forward).normalized();
+        let up = forward.cross(&right);
+        let mut res = Mat4x4::identity();
+        for (row, axis) in res.content.iter_mut().zip([right, up, forward]) {
+            *row = [axis.x, axis.y, axis.z, -axis.dot(eye)];
+        }
+        return res;
+    }
+
+    /// Perspective projection of a camera looking along +z, with the vertical field of view in
+    /// degrees. Maps depth between the near and far planes to z/w between 0 and 1.
+    pub fn perspective(vertical_fov: f32, aspect_ratio: f32, z_near: f32, z_far: f32) -> Mat4x4 {
+        let mut res = Mat4x4::default();
+        let half_vertical_fov = vertical_fov.to_radians() / 2.0;
+        res.content[0][0] = 1.0 / (half_vertical_fov.tan() * aspect_ratio);
+        res.content[1][1] = 1.0 / (half_vertical_fov.tan());
+        res.content[2][2] = z_far / (z_far - z_near);
+        res.content[2][3] = -z_far * z_near / (z_far - z_near);
+        res.content[3][2] = 1.0;
+        return res;
+    }
+
+    /// Parallel projection of the box between `left` and `right`, `bottom` and `top`, and the near
+    /// and far planes, with the same clip space as `perspective`.
+    pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, z_near: f32, z_far: f32) -> Mat4x4 {
+        let mut res = Mat4x4::identity();
+        res.content[0][0] = 2.0 / (right - left);
+        res.content[0][3] = -(right + left) / (right - left);
+        res.content[1][1] = 2.0 / (top - bottom);
+        res.content[1][3] = -(top + bottom) / (top - bottom);
+        res.content[2][2] = 1.0 / (z_far - z_near);
+        res.content[2][3] = -z_near / (z_far - z_near);
+        return res;
+    }
+
+    pub fn transpose(&self) -> Mat4x4 {
+        let mut res = Mat4x4::default();
+        for i in 0..4 {
+            for j in 0..4 {
+                res.content[i][j] = self.content[j][i];
+            }
+        }
+        return res;
+    }
+
+    /// Determinants of the 2x2 submatrices in the top two rows and in the bottom two rows, from
+    /// which both the determinant and the inverse are built.
+    fn sub_determinants(&self) -> ([f32; 6], [f32; 6]) {
+        let a = &self.content;
+        let top = [
+            a[0][0] * a[1][1] - a[1][0] * a[0][1],
+            a[0][0] * a[1][2] - a[1][0] * a[0][2],
+            a[0][0] * a[1][3] - a[1][0] * a[0][3],
+            a[0][1] * a[1][2] - a[1][1] * a[0][2],
+            a[0][1] * a[1][3] - a[1][1] * a[0][3],
+            a[0][2] * a[1][3] - a[1][2] * a[0][3],
+        ];
+        let bottom = [
+            a[2][0] * a[3][1] - a[3][0] * a[2][1],
+            a[2][0] * a[3][2] - a[3][0] * a[2][2],
+            a[2][0] * a[3][3] - a[3][0] * a[2][3],
+            a[2][1] * a[3][2] - a[3][1] * a[2][2],
+            a[2][1] * a[3][3] - a[3][1] * a[2][3],
+            a[2][2] * a[3][3] - a[3][2] * a[2][3],
+        ];
+        return (top, bottom);
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let (s, c) = self.sub_determinants();
+        return s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0];
+    }
+
+    /// Inverse by cofactor expansion, or None if the matrix is singular.
+    pub fn inverse(&self) -> Option<Mat4x4> {
+        let (s, c) = self.sub_determinants();
+        let determinant = s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0];
+        if determinant == 0.0 || !determinant.is_finite() {
+            return None;
+        }
+        let a = &self.content;
+        let adjugate = [
+            [
+                a[1][1] * c[5] - a[1][2] * c[4] + a[1][3] * c[3],
+                -a[0][1] * c[5] + a[0][2] * c[4] - a[0][3] * c[3],
+                a[3][1] * s[5] - a[3][2] * s[4] + a[3][3] * s[3],
+                -a[2][1] * s[5] + a[2][2] * s[4] - a[2][3] * s[3],
+            ],
+            [
+                -a[1][0] * c[5] + a[1][2] * c[2] - a[1][3] * c[1],
+                a[0][0] * c[5] - a[0][2] * c[2] + a[0][3] * c[1],
+                -a[3][0] * s[5] + a[3][2] * s[2] - a[3][3] * s[1],
+                a[2][0] * s[5] - a[2][2] * s[2] + a[2][3] * s[1],
+            ],
+            [
+                a[1][0] * c[4] - a[1][1] * c[2] + a[1][3] * c[0],
+                -a[0][0] * c[4] + a[0][1] * c[2] - a[0][3] * c[0],
+                a[3][0] * s[4] - a[3][1] * s[2] + a[3][3] * s[0],
+                -a[2][0] * s[4] + a[2][1] * s[2] - a[2][3] * s[0],
+            ],
+            [
+                -a[1][0] * c[3] + a[1][1] * c[1] - a[1][2] * c[0],
+                a[0][0] * c[3] - a[0][1] * c[1] + a[0][2] * c[0],
+                -a[3][0] * s[3] + a[3][1] * s[1] - a[3][2] * s[0],
+                a[2][0] * s[3] - a[2][1] * s[1] + a[2][2] * s[0],
+            ],
+        ];
+        return Some(Mat4x4 { content: adjugate.map(|row| row.map(|value| value / determinant)) });
+    }
+
+    /// Inverse transpose of the upper-left 3x3 part, which keeps transformed normals perpendicular
+    /// to transformed surfaces under non-uniform scale. A singular matrix has no such inverse, and
+    /// its own 3x3 part is returned instead.
+    pub fn normal_matrix(&self) -> Mat3x3 {
+        let source = self.inverse().unwrap_or_else(|| self.transpose());
+        let mut res = Mat3x3::default();
+        for i in 0..3 {
+            for j in 0..3 {
+                res.content[i][j] = source.content[j][i];
+            }
+        }
+        return res;
+    }
+
+    /// Rotation by Euler angles in degrees around x, then y, then z.
     // cosycosz sinxsinycosz−cosxsinz cosxsinycosz+sinxsinz
     // cosysinz sinxsinysinz+cosxcosz cosxsinysinz−sinxcosz
     // −siny sinxcosy cosxcosy
@@ -199,6 +622,12 @@ impl Mat4x4 {
     }
 }
 
+impl Default for Vec2<f32> {
+    fn default() -> Self {
+        Vec2::new(0.0, 0.0)
+    }
+}
+
 impl Default for Vec3 {
     fn default() -> Self {
         Vec3::new(0.0, 0.0, 0.0)
@@ -211,6 +640,22 @@ impl Default for Vec4 {
     }
 }
 
+impl Default for Quat {
+    fn default() -> Self {
+        Quat::IDENTITY
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::default(),
+            rotation: Quat::IDENTITY,
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
 impl Default for Mat3x3 {
     fn default() -> Self {
         Self {
@@ -242,15 +687,81 @@ impl Mul<&Mat4x4> for &Mat4x4 {
     type Output = Mat4x4;
 
     fn mul(self, rhs: &Mat4x4) -> Self::Output {
-        let mut res = Mat4x4::default();
-        for target_i in 0..4 {
-            for target_j in 0..4 {
-                for shift in 0..4 {
-                    res.content[target_i][target_j] += self.content[target_i][shift] * rhs.content[shift][target_j];
-                }
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        return simd::mat_mul_mat(self, rhs);
+        #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+        return mat_mul_mat_scalar(self, rhs);
+    }
+}
+
+// the scalar products stay available with the `simd` feature to benchmark against
+#[cfg_attr(all(feature = "simd", target_arch = "x86_64"), allow(dead_code))]
+fn mat_mul_mat_scalar(lhs: &Mat4x4, rhs: &Mat4x4) -> Mat4x4 {
+    let mut res = Mat4x4::default();
+    for target_i in 0..4 {
+        for target_j in 0..4 {
+            for shift in 0..4 {
+                res.content[target_i][target_j] += lhs.content[target_i][shift] * rhs.content[shift][target_j];
             }
         }
-        return res;
+    }
+    return res;
+}
+
+#[cfg_attr(all(feature = "simd", target_arch = "x86_64"), allow(dead_code))]
+fn mat_mul_vec_scalar(mat: &Mat4x4, vec: &Vec4) -> Vec4 {
+    let content = &mat.content;
+    return Vec4::new(
+        content[0][0] * vec.x + content[0][1] * vec.y + content[0][2] * vec.z + content[0][3] * vec.w,
+        content[1][0] * vec.x + content[1][1] * vec.y + content[1][2] * vec.z + content[1][3] * vec.w,
+        content[2][0] * vec.x + content[2][1] * vec.y + content[2][2] * vec.z + content[2][3] * vec.w,
+        content[3][0] * vec.x + content[3][1] * vec.y + content[3][2] * vec.z + content[3][3] * vec.w,
+    );
+}
+
+// the 3D vector operations work on x, y and z, and keep the w of the left operand unless noted
+#[cfg_attr(all(feature = "simd", target_arch = "x86_64"), allow(dead_code))]
+fn vec_add_scalar(lhs: &Vec4, rhs: &Vec4) -> Vec4 {
+    return Vec4::new(lhs.x + rhs.x, lhs.y + rhs.y, lhs.z + rhs.z, lhs.w);
+}
+
+#[cfg_attr(all(feature = "simd", target_arch = "x86_64"), allow(dead_code))]
+fn vec_sub_scalar(lhs: &Vec4, rhs: &Vec4) -> Vec4 {
+    return Vec4::new(lhs.x - rhs.x, lhs.y - rhs.y, lhs.z - rhs.z, lhs.w);
+}
+
+#[cfg_attr(all(feature = "simd", target_arch = "x86_64"), allow(dead_code))]
+fn vec_scale_scalar(vec: &Vec4, factor: f32) -> Vec4 {
+    return Vec4::new(vec.x * factor, vec.y * factor, vec.z * factor, vec.w);
+}
+
+#[cfg_attr(all(feature = "simd", target_arch = "x86_64"), allow(dead_code))]
+fn vec_dot_scalar(lhs: &Vec4, rhs: &Vec4) -> f32 {
+    return lhs.x * rhs.x + lhs.y * rhs.y + lhs.z * rhs.z;
+}
+
+/// w is 1 in the result.
+#[cfg_attr(all(feature = "simd", target_arch = "x86_64"), allow(dead_code))]
+fn vec_cross_scalar(lhs: &Vec4, rhs: &Vec4) -> Vec4 {
+    return Vec4::new3d(lhs.y * rhs.z - lhs.z * rhs.y,
+                       lhs.z * rhs.x - lhs.x * rhs.z,
+                       lhs.x * rhs.y - lhs.y * rhs.x);
+}
+
+/// w is 1 in the result.
+#[cfg_attr(all(feature = "simd", target_arch = "x86_64"), allow(dead_code))]
+fn vec_normalized_scalar(vec: &Vec4) -> Vec4 {
+    let len = vec.len();
+    return Vec4::new3d(vec.x / len, vec.y / len, vec.z / len);
+}
+
+#[cfg_attr(all(feature = "simd", target_arch = "x86_64"), allow(dead_code))]
+fn vec_perspective_div_scalar(vec: &mut Vec4) {
+    if vec.w != 0.0 {
+        vec.x /= vec.w;
+        vec.y /= vec.w;
+        vec.z /= vec.w;
+        vec.w = 1.0 / vec.w;
     }
 }
 
@@ -267,17 +778,39 @@ impl Mul<&Vec3> for &Mat3x3 {
     }
 }
 
+impl Mul<&Quat> for &Quat {
+    type Output = Quat;
+
+    /// Rotation by `rhs` followed by `self`.
+    fn mul(self, rhs: &Quat) -> Self::Output {
+        Quat {
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+        }
+    }
+}
+
+impl Mul<&Vec3> for &Quat {
+    type Output = Vec3;
+
+    fn mul(self, rhs: &Vec3) -> Self::Output {
+        // v + 2w(q × v) + 2q × (q × v) for the vector part q
+        let q = Vec3::new(self.x, self.y, self.z);
+        let t = &q.cross(rhs) * 2.0;
+        return &(rhs + &(&t * self.w)) + &q.cross(&t);
+    }
+}
+
 impl Mul<&Vec4> for &Mat4x4 {
     type Output = Vec4;
 
     fn mul(self, rhs: &Vec4) -> Self::Output {
-        let mut res = Vec4::default();
-        let content = self.content;
-        res.x = content[0][0] * rhs.x + content[0][1] * rhs.y + content[0][2] * rhs.z + content[0][3] * rhs.w;
-        res.y = content[1][0] * rhs.x + content[1][1] * rhs.y + content[1][2] * rhs.z + content[1][3] * rhs.w;
-        res.z = content[2][0] * rhs.x + content[2][1] * rhs.y + content[2][2] * rhs.z + content[2][3] * rhs.w;
-        res.w = content[3][0] * rhs.x + content[3][1] * rhs.y + content[3][2] * rhs.z + content[3][3] * rhs.w;
-        return res;
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        return simd::mat_mul_vec(self, rhs);
+        #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+        return mat_mul_vec_scalar(self, rhs);
     }
 }
 
@@ -295,19 +828,16 @@ impl Mul<f32> for &Vec4 {
     type Output = Vec4;
 
     fn mul(self, rhs: f32) -> Self::Output {
-        let mut res = self.clone();
-        res.x *= rhs;
-        res.y *= rhs;
-        res.z *= rhs;
-        return res;
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        return simd::vec_scale(self, rhs);
+        #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+        return vec_scale_scalar(self, rhs);
     }
 }
 
 impl MulAssign<f32> for &mut Vec4 {
     fn mul_assign(&mut self, rhs: f32) {
-        self.x *= rhs;
-        self.y *= rhs;
-        self.z *= rhs;
+        **self = &**self * rhs;
     }
 }
 
@@ -315,7 +845,18 @@ impl Add<&Vec4> for &Vec4 {
     type Output = Vec4;
 
     fn add(self, rhs: &Vec4) -> Self::Output {
-        Vec4::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z, self.w)
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        return simd::vec_add(self, rhs);
+        #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+        return vec_add_scalar(self, rhs);
+    }
+}
+
+impl Sub<&Vec2<f32>> for &Vec2<f32> {
+    type Output = Vec2<f32>;
+
+    fn sub(self, rhs: &Vec2<f32>) -> Self::Output {
+        Vec2::new(self.x - rhs.x, self.y - rhs.y)
     }
 }
 
@@ -323,7 +864,34 @@ impl Sub<&Vec4> for &Vec4 {
     type Output = Vec4;
 
     fn sub(self, rhs: &Vec4) -> Self::Output {
-        Vec4::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z, self.w)
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        return simd::vec_sub(self, rhs);
+        #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+        return vec_sub_scalar(self, rhs);
+    }
+}
+
+impl Sub<&Vec3> for &Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, rhs: &Vec3) -> Self::Output {
+        Vec3::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl Add<&Vec3> for &Vec3 {
+    type Output = Vec3;
+
+    fn add(self, rhs: &Vec3) -> Self::Output {
+        Vec3::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl Mul<f32> for &Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Vec3::new(self.x * rhs, self.y * rhs, self.z * rhs)
     }
 }
 
@@ -337,21 +905,7 @@ impl MulAssign<f32> for Vec3 {
 
 impl Triangle {
     pub const fn new(p1: Vec4, p2: Vec4, p3: Vec4) -> Self {
-        Self { p1, p2, p3, world_normal: None }
-    }
-
-    pub const fn new_with_normal(p1: Vec4, p2: Vec4, p3: Vec4, world_normal: Option<Vec4>) -> Self {
-        Self { p1, p2, p3, world_normal }
-    }
-
-    pub fn clockwise(&self) -> Self {
-        let mut res = self.clone();
-        let v12 = &self.p2 - &self.p1;
-        let v13 = &self.p3 - &self.p1;
-        if v12.cross_len_2d(&v13) > 0.0 {
-            swap(&mut res.p1, &mut res.p2);
-        }
-        return res;
+        Self { p1, p2, p3 }
     }
 }
 
@@ -391,18 +945,310 @@ impl Neg for &Vec3 {
     }
 }
 
-impl Plane for Vec4 {
-    fn new_plane(point: Vec4, mut normal: Vec4) -> Self {
-        normal.w = -point.dot(&normal);
-        return normal;
+/// Splits a simple (possibly concave) planar polygon into triangles by ear clipping.
+/// Returned triangles index into `points` and keep the polygon's winding.
+pub fn triangulate_polygon(points: &[Vec4]) -> Vec<[usize; 3]> {
+    if points.len() < 3 {
+        return vec![];
     }
 
-    fn is_point_inside(&self, point: &Vec4) -> bool {
-        self.dot(point) + self.w >= 0.0
+    // Newell's method gives a robust polygon normal even for concave polygons
+    let mut normal = Vec4::new(0.0, 0.0, 0.0, 0.0);
+    for (i, cur) in points.iter().enumerate() {
+        let next = &points[(i + 1) % points.len()];
+        normal.x += (cur.y - next.y) * (cur.z + next.z);
+        normal.y += (cur.z - next.z) * (cur.x + next.x);
+        normal.z += (cur.x - next.x) * (cur.y + next.y);
     }
 
-    fn intersect_with_segment(&self, p1: &Vec4, p2: &Vec4) -> Vec4 {
-        let t = (-self.w - self.dot(p1)) / self.dot(&(p2 - p1));
-        return p1 + &(&(p2 - p1) * t);
+    // project onto the plane of the two axes least aligned with the normal, keeping counter-clockwise order
+    let (abs_x, abs_y, abs_z) = (normal.x.abs(), normal.y.abs(), normal.z.abs());
+    let projected: Vec<Vec4> = points.iter().map(|p| {
+        if abs_x >= abs_y && abs_x >= abs_z {
+            Vec4::new3d(p.y, p.z, 0.0)
+        } else if abs_y >= abs_z {
+            Vec4::new3d(p.z, p.x, 0.0)
+        } else {
+            Vec4::new3d(p.x, p.y, 0.0)
+        }
+    }).collect();
+    let dominant = if abs_x >= abs_y && abs_x >= abs_z {
+        normal.x
+    } else if abs_y >= abs_z {
+        normal.y
+    } else {
+        normal.z
+    };
+    let orientation = if dominant < 0.0 { -1.0 } else { 1.0 };
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let ear = [remaining[(i + count - 1) % count], remaining[i], remaining[(i + 1) % count]];
+            let [prev, cur, next] = ear.map(|ind| &projected[ind]);
+            if (cur - prev).cross_len_2d(&(next - cur)) * orientation <= 0.0 {
+                return false;
+            }
+            return remaining.iter()
+                .filter(|other| !ear.contains(other))
+                .all(|&other| !is_point_in_triangle_2d(&projected[other], prev, cur, next));
+        });
+        // a degenerate polygon has no ears left, so fall back to a fan for whatever remains
+        let Some(ear) = ear else {
+            break;
+        };
+        triangles.push([remaining[(ear + count - 1) % count], remaining[ear], remaining[(ear + 1) % count]]);
+        remaining.remove(ear);
     }
-}
\ No newline at end of file
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+    return triangles;
+}
+
+fn is_point_in_triangle_2d(p: &Vec4, a: &Vec4, b: &Vec4, c: &Vec4) -> bool {
+    let d1 = (b - a).cross_len_2d(&(p - a));
+    let d2 = (c - b).cross_len_2d(&(p - b));
+    let d3 = (a - c).cross_len_2d(&(p - c));
+    let has_negative = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+    let has_positive = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+    return !(has_negative && has_positive);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hint::black_box;
+    use std::time::Instant;
+
+    fn test_points(count: usize) -> Vec<Vec4> {
+        return (0..count).map(|i| {
+            let i = i as f32;
+            Vec4::new((i * 0.37).sin() * 10.0, (i * 0.11).cos() * 5.0, i * 0.01 - 3.0, 1.0 - (i % 2.0))
+        }).collect();
+    }
+
+    fn test_matrix() -> Mat4x4 {
+        let perspective = Mat4x4 {
+            content: [[1.3, 0.0, 0.0, 0.0], [0.0, 1.7, 0.0, 0.0], [0.0, 0.0, 1.02, -0.1], [0.0, 0.0, 1.0, 0.0]],
+        };
+        let model = &Mat4x4::translation(&Vec3::new(0.5, -2.0, 4.0)) * &Mat4x4::rotation(&Vec3::new(20.0, 35.0, -70.0));
+        return &perspective * &model;
+    }
+
+    fn bits(vec: &Vec4) -> [u32; 4] {
+        return [vec.x, vec.y, vec.z, vec.w].map(f32::to_bits);
+    }
+
+    #[test]
+    fn products_match_the_scalar_code_exactly() {
+        let mat = test_matrix();
+        let other = &Mat4x4::rotation(&Vec3::new(-5.0, 80.0, 12.0)) * &Mat4x4::translation(&Vec3::new(3.0, 1.0, -1.5));
+        let (product, scalar_product) = (&mat * &other, mat_mul_mat_scalar(&mat, &other));
+        for (row, scalar_row) in product.content.iter().zip(&scalar_product.content) {
+            assert_eq!(row.map(f32::to_bits), scalar_row.map(f32::to_bits));
+        }
+
+        // an odd count also covers the points left over after transforming pairs
+        let points = test_points(101);
+        let mut transformed = points.clone();
+        mat.transform_all(&mut transformed);
+        for (point, transformed) in points.iter().zip(&transformed) {
+            assert_eq!(bits(&(&mat * point)), bits(transformed));
+            assert_eq!(bits(&mat_mul_vec_scalar(&mat, point)), bits(transformed));
+        }
+    }
+
+    #[test]
+    fn vector_operations_match_the_scalar_code_exactly() {
+        let mut points = test_points(50);
+        points.push(Vec4::new(2.5, -1.0, 0.5, 0.0));
+        for (lhs, rhs) in points.iter().zip(points.iter().rev()) {
+            assert_eq!(bits(&(lhs + rhs)), bits(&vec_add_scalar(lhs, rhs)));
+            assert_eq!(bits(&(lhs - rhs)), bits(&vec_sub_scalar(lhs, rhs)));
+            assert_eq!(bits(&(lhs * rhs.x)), bits(&vec_scale_scalar(lhs, rhs.x)));
+            assert_eq!(lhs.dot(rhs).to_bits(), vec_dot_scalar(lhs, rhs).to_bits());
+            assert_eq!(bits(&lhs.cross(rhs)), bits(&vec_cross_scalar(lhs, rhs)));
+            assert_eq!(bits(&lhs.normalized()), bits(&vec_normalized_scalar(lhs)));
+            let (mut divided, mut scalar_divided) = (lhs.clone(), lhs.clone());
+            divided.perspective_div();
+            vec_perspective_div_scalar(&mut scalar_divided);
+            assert_eq!(bits(&divided), bits(&scalar_divided));
+        }
+    }
+
+    fn assert_near(actual: &Vec3, expected: &Vec3) {
+        let distance = (actual + &-expected).len();
+        assert!(distance < 1e-5, "{:?} is not {:?}", actual, expected);
+    }
+
+    #[test]
+    fn euler_quaternions_match_rotation_matrices() {
+        for angles in [Vec3::new(30.0, 0.0, 0.0), Vec3::new(0.0, -75.0, 0.0), Vec3::new(25.0, 35.0, 160.0)] {
+            let quat = Quat::from_euler(&angles);
+            let (quat_mat, euler_mat) = (quat.to_mat4(), Mat4x4::rotation(&angles));
+            for (quat_row, euler_row) in quat_mat.content.iter().zip(&euler_mat.content) {
+                for (a, b) in quat_row.iter().zip(euler_row) {
+                    assert!((a - b).abs() < 1e-5, "{:?} is not {:?}", quat_mat.content, euler_mat.content);
+                }
+            }
+            let point = Vec3::new(0.3, -1.2, 2.0);
+            assert_near(&(&quat * &point), &(&quat.to_mat3() * &point));
+        }
+    }
+
+    #[test]
+    fn quaternions_compose_interpolate_and_look() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let quarter_turn = Quat::from_axis_angle(&up, 90.0);
+        assert_near(&(&quarter_turn * &Vec3::new(1.0, 0.0, 0.0)), &Vec3::new(0.0, 0.0, -1.0));
+        let half_turn = &quarter_turn * &quarter_turn;
+        assert_near(&(&half_turn * &Vec3::new(1.0, 0.0, 0.0)), &Vec3::new(-1.0, 0.0, 0.0));
+        assert_near(&(&(&half_turn * &half_turn.inverse()) * &Vec3::new(1.0, 2.0, 3.0)), &Vec3::new(1.0, 2.0, 3.0));
+
+        let halfway = Quat::IDENTITY.slerp(&quarter_turn, 0.5);
+        let expected = Quat::from_axis_angle(&up, 45.0);
+        assert!((halfway.dot(&expected).abs() - 1.0).abs() < 1e-6);
+        // -q is the same rotation as q, and slerp still takes the short way to it
+        let negated = Quat { x: -quarter_turn.x, y: -quarter_turn.y, z: -quarter_turn.z, w: -quarter_turn.w };
+        assert!((Quat::IDENTITY.slerp(&negated, 0.5).dot(&expected).abs() - 1.0).abs() < 1e-6);
+
+        let forward = Vec3::new(1.0, 1.0, -1.0).normalized();
+        let look = Quat::look_rotation(&forward, &up);
+        assert_near(&(&look * &Vec3::new(0.0, 0.0, 1.0)), &forward);
+        // the camera's right stays horizontal
+        assert!((&look * &Vec3::new(1.0, 0.0, 0.0)).y.abs() < 1e-6);
+    }
+
+    #[test]
+    fn transform_scales_then_rotates_then_translates() {
+        let transform = Transform {
+            translation: Vec3::new(0.0, 0.0, 5.0),
+            rotation: Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), 90.0),
+            scale: Vec3::new(2.0, 1.0, 3.0),
+        };
+        let point = &transform.matrix() * &Vec4::new3d(1.0, 1.0, 0.0);
+        assert_near(&Vec3::new(point.x, point.y, point.z), &Vec3::new(0.0, 1.0, 3.0));
+        let back = &transform.matrix().inverse().unwrap() * &point;
+        assert_near(&Vec3::new(back.x, back.y, back.z), &Vec3::new(1.0, 1.0, 0.0));
+    }
+
+    fn assert_mat_near(actual: &Mat4x4, expected: &Mat4x4) {
+        for (actual_row, expected_row) in actual.content.iter().zip(&expected.content) {
+            for (a, b) in actual_row.iter().zip(expected_row) {
+                assert!((a - b).abs() < 1e-4, "{:?} is not {:?}", actual.content, expected.content);
+            }
+        }
+    }
+
+    fn to_vec3(vec: &Vec4) -> Vec3 {
+        return Vec3::new(vec.x / vec.w, vec.y / vec.w, vec.z / vec.w);
+    }
+
+    #[test]
+    fn inverse_and_transpose_round_trip() {
+        let mat = test_matrix();
+        let identity = Mat4x4::identity();
+        let inverse = mat.inverse().unwrap();
+        assert_mat_near(&(&mat * &inverse), &identity);
+        assert_mat_near(&(&inverse * &mat), &identity);
+        assert_mat_near(&inverse.inverse().unwrap(), &mat);
+        assert_mat_near(&mat.transpose().transpose(), &mat);
+        assert_mat_near(&mat.transpose().inverse().unwrap(), &inverse.transpose());
+
+        let other = Transform {
+            translation: Vec3::new(1.0, 2.0, 3.0),
+            rotation: Quat::from_euler(&Vec3::new(10.0, 20.0, 30.0)),
+            scale: Vec3::new(2.0, 0.5, 3.0),
+        }.matrix();
+        assert!((other.determinant() - 3.0).abs() < 1e-5);
+        let product_determinant = (&mat * &other).determinant();
+        assert!((product_determinant - mat.determinant() * other.determinant()).abs() < 1e-4 * product_determinant.abs());
+        assert!((mat.transpose().determinant() - mat.determinant()).abs() < 1e-5);
+
+        let mut singular = Mat4x4::identity();
+        singular.content[2] = singular.content[1];
+        assert_eq!(singular.determinant(), 0.0);
+        assert!(singular.inverse().is_none());
+    }
+
+    #[test]
+    fn view_and_projection_matrices() {
+        let eye = Vec3::new(1.0, 2.0, -3.0);
+        let target = Vec3::new(-2.0, 0.0, 4.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let view = Mat4x4::look_at(&eye, &target, &up);
+        assert_near(&to_vec3(&(&view * &Vec4::new3d(eye.x, eye.y, eye.z))), &Vec3::default());
+        let distance = (&target - &eye).len();
+        assert_near(&to_vec3(&(&view * &Vec4::new3d(target.x, target.y, target.z))), &Vec3::new(0.0, 0.0, distance));
+        // the view matrix of a camera is the inverse of its placement
+        let camera = Transform { translation: eye, rotation: Quat::look_rotation(&(&target - &eye), &up), ..Transform::default() };
+        assert_mat_near(&view, &camera.matrix().inverse().unwrap());
+
+        let perspective = Mat4x4::perspective(90.0, 2.0, 0.5, 10.0);
+        assert_near(&to_vec3(&(&perspective * &Vec4::new3d(0.0, 0.0, 0.5))), &Vec3::new(0.0, 0.0, 0.0));
+        assert_near(&to_vec3(&(&perspective * &Vec4::new3d(0.0, 0.0, 10.0))), &Vec3::new(0.0, 0.0, 1.0));
+        assert_near(&to_vec3(&(&perspective * &Vec4::new3d(4.0, 2.0, 2.0))), &Vec3::new(1.0, 1.0, 15.0 / 19.0));
+
+        let orthographic = Mat4x4::orthographic(-4.0, 2.0, -1.0, 3.0, 1.0, 5.0);
+        assert_near(&to_vec3(&(&orthographic * &Vec4::new3d(-4.0, -1.0, 1.0))), &Vec3::new(-1.0, -1.0, 0.0));
+        assert_near(&to_vec3(&(&orthographic * &Vec4::new3d(2.0, 3.0, 5.0))), &Vec3::new(1.0, 1.0, 1.0));
+        assert_near(&to_vec3(&(&orthographic * &Vec4::new3d(-1.0, 1.0, 3.0))), &Vec3::new(0.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn normal_matrix_keeps_normals_perpendicular() {
+        let mat = Transform {
+            translation: Vec3::new(5.0, -1.0, 0.0),
+            rotation: Quat::from_euler(&Vec3::new(40.0, -15.0, 70.0)),
+            scale: Vec3::new(3.0, 0.5, 1.0),
+        }.matrix();
+        // a tilted plane with two directions along it and its normal
+        let (along1, along2) = (Vec4::new(1.0, 1.0, 0.0, 0.0), Vec4::new(0.0, 1.0, -1.0, 0.0));
+        let normal = along1.cross(&along2);
+        let normal = &mat.normal_matrix() * &Vec3::new(normal.x, normal.y, normal.z);
+        for along in [along1, along2] {
+            let along = &mat * &along;
+            assert!(normal.dot(&Vec3::new(along.x, along.y, along.z)).abs() < 1e-5);
+        }
+    }
+
+    /// Compares the scalar products with the ones in use, run with
+    /// `cargo test --release --features simd -- --ignored --nocapture transform_benchmark`.
+    #[test]
+    #[ignore]
+    fn transform_benchmark() {
+        let mat = test_matrix();
+        let points = test_points(100_000);
+        let rounds = 200;
+        let time = |name: &str, transform: &dyn Fn(&mut [Vec4])| {
+            let mut transformed = points.clone();
+            let start = Instant::now();
+            for _ in 0..rounds {
+                transformed.clone_from_slice(&points);
+                transform(black_box(&mut transformed));
+            }
+            let nanoseconds = start.elapsed().as_nanos() as f64 / (rounds * points.len()) as f64;
+            println!("{:<24} {:.2} ns per point", name, nanoseconds);
+        };
+        time("scalar, one by one", &|points| {
+            points.iter_mut().for_each(|point| *point = mat_mul_vec_scalar(&mat, point));
+        });
+        time("Mul, one by one", &|points| points.iter_mut().for_each(|point| *point = &mat * &*point));
+        time("transform_all", &|points| mat.transform_all(points));
+
+        let start = Instant::now();
+        let mut product = Mat4x4::translation(&Vec3::default());
+        for _ in 0..points.len() {
+            product = mat_mul_mat_scalar(black_box(&product), &mat);
+        }
+        println!("{:<24} {:.2} ns per product", "scalar matrix product", start.elapsed().as_nanos() as f64 / points.len() as f64);
+        let start = Instant::now();
+        for _ in 0..points.len() {
+            product = black_box(&product) * &mat;
+        }
+        println!("{:<24} {:.2} ns per product", "Mul matrix product", start.elapsed().as_nanos() as f64 / points.len() as f64);
+    }
+}
@@ -0,0 +1,159 @@
+//! SSE and AVX versions of the `Vec4` operations and `Mat4x4` products. Every sum is taken in the
+//! same order as the scalar code and without fused multiply-add, so the results are bit-identical to it.
+
+use crate::math::{Mat4x4, Vec4};
+use std::arch::x86_64::*;
+
+pub fn mat_mul_mat(lhs: &Mat4x4, rhs: &Mat4x4) -> Mat4x4 {
+    let mut res = Mat4x4::default();
+    // SSE is part of every x86_64 target
+    unsafe {
+        let rhs_rows = rhs.content.map(|row| _mm_loadu_ps(row.as_ptr()));
+        for (lhs_row, res_row) in lhs.content.iter().zip(res.content.iter_mut()) {
+            let mut sum = _mm_setzero_ps();
+            for (&factor, rhs_row) in lhs_row.iter().zip(rhs_rows) {
+                sum = _mm_add_ps(sum, _mm_mul_ps(_mm_set1_ps(factor), rhs_row));
+            }
+            _mm_storeu_ps(res_row.as_mut_ptr(), sum);
+        }
+    }
+    return res;
+}
+
+pub fn mat_mul_vec(mat: &Mat4x4, vec: &Vec4) -> Vec4 {
+    let mut res = Vec4::default();
+    unsafe {
+        let vec = load_vec(vec);
+        let c = &mat.content;
+        let m0 = _mm_mul_ps(_mm_loadu_ps(c[0].as_ptr()), vec);
+        let m1 = _mm_mul_ps(_mm_loadu_ps(c[1].as_ptr()), vec);
+        let m2 = _mm_mul_ps(_mm_loadu_ps(c[2].as_ptr()), vec);
+        let m3 = _mm_mul_ps(_mm_loadu_ps(c[3].as_ptr()), vec);
+        // transpose so that each register holds the same product of all four rows
+        let t0 = _mm_unpacklo_ps(m0, m1);
+        let t1 = _mm_unpackhi_ps(m0, m1);
+        let t2 = _mm_unpacklo_ps(m2, m3);
+        let t3 = _mm_unpackhi_ps(m2, m3);
+        let x_products = _mm_movelh_ps(t0, t2);
+        let y_products = _mm_movehl_ps(t2, t0);
+        let z_products = _mm_movelh_ps(t1, t3);
+        let w_products = _mm_movehl_ps(t3, t1);
+        let sum = _mm_add_ps(_mm_add_ps(_mm_add_ps(x_products, y_products), z_products), w_products);
+        store_vec(&mut res, sum);
+    }
+    return res;
+}
+
+/// Multiplies every point by `mat`, two at a time with AVX when the CPU has it.
+pub fn transform_all(mat: &Mat4x4, points: &mut [Vec4]) {
+    if is_x86_feature_detected!("avx") {
+        unsafe { transform_all_avx(mat, points) };
+    } else {
+        unsafe { transform_all_sse(mat, points) };
+    }
+}
+
+unsafe fn transform_all_sse(mat: &Mat4x4, points: &mut [Vec4]) {
+    let columns = columns(mat);
+    for point in points {
+        let vec = load_vec(point);
+        let mut sum = _mm_mul_ps(columns[0], _mm_shuffle_ps(vec, vec, 0x00));
+        sum = _mm_add_ps(sum, _mm_mul_ps(columns[1], _mm_shuffle_ps(vec, vec, 0x55)));
+        sum = _mm_add_ps(sum, _mm_mul_ps(columns[2], _mm_shuffle_ps(vec, vec, 0xAA)));
+        sum = _mm_add_ps(sum, _mm_mul_ps(columns[3], _mm_shuffle_ps(vec, vec, 0xFF)));
+        store_vec(point, sum);
+    }
+}
+
+#[target_feature(enable = "avx")]
+unsafe fn transform_all_avx(mat: &Mat4x4, points: &mut [Vec4]) {
+    // both 128-bit lanes hold the same column, and each lane transforms one point
+    let columns = columns(mat).map(|column| _mm256_set_m128(column, column));
+    let mut pairs = points.chunks_exact_mut(2);
+    for pair in &mut pairs {
+        let ptr = pair.as_mut_ptr() as *mut f32;
+        let vecs = _mm256_loadu_ps(ptr);
+        let mut sum = _mm256_mul_ps(columns[0], _mm256_permute_ps(vecs, 0x00));
+        sum = _mm256_add_ps(sum, _mm256_mul_ps(columns[1], _mm256_permute_ps(vecs, 0x55)));
+        sum = _mm256_add_ps(sum, _mm256_mul_ps(columns[2], _mm256_permute_ps(vecs, 0xAA)));
+        sum = _mm256_add_ps(sum, _mm256_mul_ps(columns[3], _mm256_permute_ps(vecs, 0xFF)));
+        _mm256_storeu_ps(ptr, sum);
+    }
+    transform_all_sse(mat, pairs.into_remainder());
+}
+
+unsafe fn columns(mat: &Mat4x4) -> [__m128; 4] {
+    let c = &mat.content;
+    return [0, 1, 2, 3].map(|j| _mm_set_ps(c[3][j], c[2][j], c[1][j], c[0][j]));
+}
+
+pub fn vec_add(lhs: &Vec4, rhs: &Vec4) -> Vec4 {
+    let mut res = Vec4::default();
+    unsafe { store_vec(&mut res, _mm_add_ps(load_vec(lhs), load_vec(rhs))) };
+    res.w = lhs.w;
+    return res;
+}
+
+pub fn vec_sub(lhs: &Vec4, rhs: &Vec4) -> Vec4 {
+    let mut res = Vec4::default();
+    unsafe { store_vec(&mut res, _mm_sub_ps(load_vec(lhs), load_vec(rhs))) };
+    res.w = lhs.w;
+    return res;
+}
+
+pub fn vec_scale(vec: &Vec4, factor: f32) -> Vec4 {
+    let mut res = Vec4::default();
+    unsafe { store_vec(&mut res, _mm_mul_ps(load_vec(vec), _mm_set1_ps(factor))) };
+    res.w = vec.w;
+    return res;
+}
+
+pub fn vec_dot(lhs: &Vec4, rhs: &Vec4) -> f32 {
+    unsafe {
+        let products = _mm_mul_ps(load_vec(lhs), load_vec(rhs));
+        let x = _mm_cvtss_f32(products);
+        let y = _mm_cvtss_f32(_mm_shuffle_ps(products, products, 0x55));
+        let z = _mm_cvtss_f32(_mm_shuffle_ps(products, products, 0xAA));
+        return x + y + z;
+    }
+}
+
+pub fn vec_cross(lhs: &Vec4, rhs: &Vec4) -> Vec4 {
+    let mut res = Vec4::default();
+    unsafe {
+        let (lhs, rhs) = (load_vec(lhs), load_vec(rhs));
+        // (y, z, x) and (z, x, y) orders of the components
+        let lhs_yzx = _mm_shuffle_ps(lhs, lhs, 0xC9);
+        let lhs_zxy = _mm_shuffle_ps(lhs, lhs, 0xD2);
+        let rhs_yzx = _mm_shuffle_ps(rhs, rhs, 0xC9);
+        let rhs_zxy = _mm_shuffle_ps(rhs, rhs, 0xD2);
+        store_vec(&mut res, _mm_sub_ps(_mm_mul_ps(lhs_yzx, rhs_zxy), _mm_mul_ps(lhs_zxy, rhs_yzx)));
+    }
+    res.w = 1.0;
+    return res;
+}
+
+pub fn vec_normalized(vec: &Vec4) -> Vec4 {
+    let mut res = Vec4::default();
+    let len = vec_dot(vec, vec).sqrt();
+    unsafe { store_vec(&mut res, _mm_div_ps(load_vec(vec), _mm_set1_ps(len))) };
+    res.w = 1.0;
+    return res;
+}
+
+pub fn vec_perspective_div(vec: &mut Vec4) {
+    if vec.w != 0.0 {
+        let w = vec.w;
+        unsafe { store_vec(vec, _mm_div_ps(load_vec(vec), _mm_set1_ps(w))) };
+        vec.w = 1.0 / w;
+    }
+}
+
+/// `Vec4` is `repr(C)`, so its four components are laid out like an array.
+unsafe fn load_vec(vec: &Vec4) -> __m128 {
+    return _mm_loadu_ps(vec as *const Vec4 as *const f32);
+}
+
+unsafe fn store_vec(vec: &mut Vec4, value: __m128) {
+    _mm_storeu_ps(vec as *mut Vec4 as *mut f32, value);
+}
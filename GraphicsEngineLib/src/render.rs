@@ -1,8 +1,14 @@
-use crate::game::Scene;
-use crate::math::{Lerp, Mat4x4, Plane, Triangle, Vec2, Vec3, Vec4};
+use crate::assets::image::Image;
+use crate::game::{GameObject, NodeId, Scene};
+use crate::lighting::{to_color, Light, SceneLighting, Shading, Shadow, ShadowCascade, ShadowMap};
+use crate::math::{Lerp, Mat4x4, Triangle, Vec2, Vec3, Vec4};
+use crate::texture::Texture;
 use crate::Color;
 use std::cmp::{max, min};
 use std::mem::swap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 
 static BACKGROUND_COLOR: Color = Color {
     red: 200,
@@ -11,19 +17,8 @@ static BACKGROUND_COLOR: Color = Color {
     alpha: 0,
 };
 
-static BLACK_COLOR: Color = Color {
-    red: 0,
-    green: 0,
-    blue: 0,
-    alpha: 0,
-};
-
-static MODEL_COLOR: Color = Color {
-    red: 200,
-    green: 200,
-    blue: 0,
-    alpha: 0,
-};
+/// Neutral texture color for untextured materials and for lighting that is textured later per pixel.
+static WHITE: Vec3 = Vec3::new(1.0, 1.0, 1.0);
 
 static WIREFRAME_LINE_COLOR: Color = Color {
     red: 50,
@@ -39,39 +34,181 @@ static WIREFRAME_POINT_COLOR: Color = Color {
     alpha: 0,
 };
 
+/// Which triangles of an object are skipped before clipping, by the side they face the camera with.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CullMode {
+    None,
+    Back,
+    Front,
+}
+
+/// Winding of front-facing triangles in the mesh, with `CounterClockwise` matching OBJ files.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrontFace {
+    CounterClockwise,
+    Clockwise,
+}
+
+/// Counters collected while rendering one frame.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    pub culled_triangles: usize,
+}
+
+/// Vertex positions are snapped to this fraction of a pixel before rasterization.
+const SUBPIXEL_SCALE: i64 = 256;
+
+/// Width and height of the square tiles the screen is split into for multithreaded rendering.
+const TILE_SIZE: i32 = 32;
+
+const WIREFRAME_POINT_RADIUS: i32 = 3;
+
+/// Weight of the logarithmic split distances against evenly spaced ones when slicing the view into
+/// shadow cascades. Logarithmic splits keep the texel size on screen the same in every cascade.
+const CASCADE_SPLIT_BLEND: f32 = 0.75;
+
+/// Fraction at the far end of every shadow cascade over which it blends into the next one.
+const CASCADE_BLEND_FRACTION: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct ScreenSize {
     pub width: i32,
     pub height: i32,
 }
 
+/// Pixels from (`x_min`, `y_min`) up to but excluding (`x_max`, `y_max`), with y pointing up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PixelRect {
+    x_min: i32,
+    y_min: i32,
+    x_max: i32,
+    y_max: i32,
+}
+
 #[derive(Clone, Copy, Debug)]
 struct DeepPixel {
     color: Color,
     depth: f32,
 }
 
-struct DepthBuffer {
+pub struct DepthBuffer {
     buffer: Vec<DeepPixel>,
     screen_size: ScreenSize,
+    /// Part of the screen stored in `buffer`, which is the whole screen except for the tiles of
+    /// multithreaded rendering. Writes outside of it are dropped.
+    region: PixelRect,
+}
+
+/// Values computed per vertex and interpolated across the triangle during rasterization.
+#[derive(Clone, Debug, Default)]
+struct Varyings {
+    /// Lit color for flat and Gouraud shading.
+    color: Vec3,
+    world_position: Vec4,
+    world_normal: Vec4,
+    uv: Vec2<f32>,
+    /// World-space directions of increasing u and v, the same for the whole triangle. Only computed
+    /// for bump mapped materials.
+    tangent: Vec4,
+    bitangent: Vec4,
+}
+
+/// Varyings of one pixel, with the uv changes towards the next pixel to the right and above.
+struct Fragment {
+    varyings: Varyings,
+    uv_dx: Vec2<f32>,
+    uv_dy: Vec2<f32>,
+}
+
+/// Triangle in clip space, or in normalized device coordinates once projected, together with the
+/// varyings of its three vertices and the indices of the drawn object and object material it belongs to.
+#[derive(Clone)]
+struct ShadedTriangle {
+    triangle: Triangle,
+    varyings: [Varyings; 3],
+    object: usize,
+    material: usize,
+}
+
+/// Vertex of a polygon being clipped, with its position in clip space.
+#[derive(Clone)]
+struct ClipVertex {
+    position: Vec4,
+    varyings: Varyings,
 }
 
 pub struct Camera {
     pub vertical_fov: f32,
     pub z_near: f32,
     pub z_far: f32,
-    pub position: Vec3,
-    pub rotation: Vec3,
+}
+
+impl ScreenSize {
+    fn rect(&self) -> PixelRect {
+        PixelRect { x_min: 0, y_min: 0, x_max: self.width, y_max: self.height }
+    }
+}
+
+impl PixelRect {
+    fn width(&self) -> i32 {
+        self.x_max - self.x_min
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x_min && x < self.x_max && y >= self.y_min && y < self.y_max
+    }
+
+    fn intersection(&self, other: &PixelRect) -> PixelRect {
+        PixelRect {
+            x_min: self.x_min.max(other.x_min),
+            y_min: self.y_min.max(other.y_min),
+            x_max: self.x_max.min(other.x_max),
+            y_max: self.y_max.min(other.y_max),
+        }
+    }
 }
 
 impl DepthBuffer {
-    fn new(screen_size: ScreenSize, color: Color) -> Self {
-        let background_pixel = DeepPixel { color, depth: 1.0 };
+    pub fn new(screen_size: ScreenSize) -> Self {
+        return Self::new_region(screen_size, screen_size.rect());
+    }
+
+    /// Creates a buffer holding only the `region` part of the screen.
+    fn new_region(screen_size: ScreenSize, region: PixelRect) -> Self {
+        let background_pixel = DeepPixel { color: BACKGROUND_COLOR, depth: 1.0 };
+        let pixel_count = (region.x_max - region.x_min) * (region.y_max - region.y_min);
         Self {
-            buffer: vec![background_pixel; (screen_size.width * screen_size.height) as usize],
+            buffer: vec![background_pixel; pixel_count as usize],
             screen_size,
+            region,
         }
     }
 
+    pub fn screen_size(&self) -> ScreenSize {
+        self.screen_size
+    }
+
+    /// Depth stored at `x`, `y`, or the cleared depth of 1 outside the buffer.
+    pub fn depth_at(&self, x: i32, y: i32) -> f32 {
+        return self.index(x, y).map_or(1.0, |ind| self.buffer[ind].depth);
+    }
+
+    fn clear(&mut self, color: Color) {
+        self.buffer.fill(DeepPixel { color, depth: 1.0 });
+    }
+
+    /// Index in `buffer` of the pixel at `x`, `y`, whose rows are stored from the top.
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if !self.region.contains(x, y) {
+            return None;
+        }
+        let row = self.region.y_max - 1 - y;
+        return Some((row * self.region.width() + x - self.region.x_min) as usize);
+    }
+
     fn screen_space_to_pixel_pos(&self, mut screen_space_x: f32, mut screen_space_y: f32) -> Vec2<i32> {
         screen_space_x = (screen_space_x + 1.0) / 2.0;
         screen_space_y = (screen_space_y + 1.0) / 2.0;
@@ -85,198 +222,683 @@ impl DepthBuffer {
         self.set_pixel(pixel_pos.x, pixel_pos.y, pixel);
     }
 
+    /// Whether a pixel at `depth` would be in front of what is stored at `x`, `y`.
+    fn passes_depth_test(&self, x: i32, y: i32, depth: f32) -> bool {
+        return depth >= 0.0 && self.index(x, y).is_some_and(|ind| depth <= self.buffer[ind].depth);
+    }
+
     fn set_pixel(&mut self, x: i32, y: i32, pixel: DeepPixel) {
-        if x < 0 || x >= self.screen_size.width ||
-            y < 0 || y >= self.screen_size.height ||
-            pixel.depth < 0.0
-        {
-            return;
-        }
+        self.blend_pixel(x, y, pixel, 1.0);
+    }
 
-        let y = self.screen_size.height - 1 - y;
-        let ind = (y * self.screen_size.width + x) as usize;
+    /// Blends the pixel color over the stored one with `opacity` if it passes the depth test.
+    /// Only fully opaque pixels update the stored depth, so surfaces behind transparent ones are
+    /// still drawn, but blended on top of them.
+    fn blend_pixel(&mut self, x: i32, y: i32, pixel: DeepPixel, opacity: f32) {
+        let Some(ind) = self.index(x, y) else {
+            return;
+        };
         let old = self.buffer[ind];
-        if pixel.depth <= old.depth {
+        if pixel.depth < 0.0 || pixel.depth > old.depth {
+            return;
+        }
+        if opacity >= 1.0 {
             self.buffer[ind] = pixel;
+        } else {
+            self.buffer[ind].color = old.color.lerp(&pixel.color, opacity);
+        }
+    }
+
+    /// Copies the pixels of `tile`, a buffer of a smaller region of the same screen, into this one.
+    fn copy_tile(&mut self, tile: &DepthBuffer) {
+        let width = tile.region.width() as usize;
+        for (row, y) in tile.buffer.chunks_exact(width).zip((tile.region.y_min..tile.region.y_max).rev()) {
+            let start = self.index(tile.region.x_min, y).unwrap();
+            self.buffer[start..start + width].copy_from_slice(row);
+        }
+    }
+
+    /// Copies the colors row by row into `pixels`, where consecutive rows start `stride` pixels apart.
+    pub fn copy_to(&self, pixels: &mut [Color], stride: usize) {
+        let width = self.screen_size.width as usize;
+        for (row, target) in self.buffer.chunks_exact(width).zip(pixels.chunks_mut(stride)) {
+            for (pixel, target) in row.iter().zip(target.iter_mut()) {
+                *target = pixel.color;
+            }
         }
     }
 
-    fn to_bitmap(&self) -> Vec<Color> {
-        self.buffer.iter().map(|dp| dp.color).collect()
+    pub fn to_image(&self) -> Image {
+        let ScreenSize { width, height } = self.screen_size;
+        let mut pixels = vec![BACKGROUND_COLOR; (width * height) as usize];
+        self.copy_to(&mut pixels, width as usize);
+        return Image { width: width as usize, height: height as usize, pixels };
     }
 }
 
 impl Camera {
-    fn render(&self, buffer: &mut DepthBuffer, scene: &Scene) {
-        let screen_size = &buffer.screen_size;
+    fn render(&self, buffer: &mut DepthBuffer, scene: &Scene, thread_count: usize,
+              previous_lighting: &mut Option<SceneLighting>) -> RenderStats {
+        let mut stats = RenderStats::default();
+        let screen_size = buffer.screen_size;
         let aspect_ratio = screen_size.width as f32 / screen_size.height as f32;
         let perspective_mat = self.perspective_mat(aspect_ratio);
-        let mut triangles = vec![];
-        for object in &scene.objects {
-            for tr in &object.mesh.triangles {
-                let mut tr = &Mat4x4::rotation(&object.rotation) * tr;
-                tr *= &Mat4x4::translation(&object.position);
+        let world_mats = scene.world_matrices();
+        let camera_mat = &world_mats[scene.camera_node];
+        let clip_mat = &perspective_mat * &Camera::view_mat(camera_mat);
 
-                let triangle_normal = (&tr.p2 - &tr.p1).cross(&(&tr.p3 - &tr.p1)).normalized();
-                tr.world_normal = Some(triangle_normal);
+        let lighting = SceneLighting::new(scene, &world_mats, aspect_ratio, previous_lighting.take());
 
-                let camera_negative_pos = -&scene.camera.position;
-                tr *= &Mat4x4::translation(&camera_negative_pos);
-                let camera_negative_rotation = -&scene.camera.rotation;
-                tr *= &Mat4x4::rotation(&camera_negative_rotation);
+        let objects: Vec<(NodeId, &GameObject)> = scene.objects().collect();
+        let mut triangles = Vec::with_capacity(objects.iter().map(|(_, o)| o.mesh.triangle_count()).sum());
+        for (object_ind, &(node, object)) in objects.iter().enumerate() {
+            let model_mat = &world_mats[node];
+            let normal_mat = model_mat.normal_matrix();
+            // a negative scale mirrors the mesh, which turns its counter-clockwise triangles clockwise
+            let winding_sign = if model_mat.determinant() < 0.0 { -1.0 } else { 1.0 };
+            let mut world_positions: Vec<Vec4> = object.mesh.vertices.iter()
+                .map(|vertex| vertex.position.clone())
+                .collect();
+            model_mat.transform_all(&mut world_positions);
+            let mut clip_positions = world_positions.clone();
+            clip_mat.transform_all(&mut clip_positions);
+            let world_normals: Vec<Option<Vec4>> = object.mesh.vertices.iter()
+                .map(|vertex| vertex.normal.as_ref().map(|normal| {
+                    let world_normal = &normal_mat * &Vec3::new(normal.x, normal.y, normal.z);
+                    let mut world_normal = Vec4::new3d(world_normal.x, world_normal.y, world_normal.z).normalized();
+                    world_normal.w = 0.0;
+                    world_normal
+                }))
+                .collect();
 
-                triangles.push(tr);
+            for submesh in &object.mesh.submeshes {
+                let material = &object.materials[submesh.material];
+                for [i1, i2, i3] in object.mesh.submesh_triangles(submesh) {
+                    let (p1, p2, p3) = (&world_positions[i1], &world_positions[i2], &world_positions[i3]);
+                    let mut triangle_normal = &(p2 - p1).cross(&(p3 - p1)).normalized() * winding_sign;
+                    triangle_normal.w = 0.0;
+                    if is_culled(&triangle_normal, p1, &lighting.camera_position, object.cull_mode, object.front_face) {
+                        stats.culled_triangles += 1;
+                        continue;
+                    }
+                    let uvs = [i1, i2, i3].map(|i| object.mesh.vertices[i].uv.unwrap_or_default());
+                    let [tangent, bitangent] = match material.bump_texture {
+                        Some(_) => uv_tangents([p1, p2, p3], &uvs),
+                        None => [Vec4::default(), Vec4::default()],
+                    };
+                    let mut varyings = [(i1, uvs[0]), (i2, uvs[1]), (i3, uvs[2])].map(|(i, uv)| Varyings {
+                        color: Vec3::default(),
+                        world_position: world_positions[i].clone(),
+                        world_normal: match object.shading {
+                            Shading::Flat => triangle_normal.clone(),
+                            Shading::Gouraud | Shading::Phong =>
+                                world_normals[i].clone().unwrap_or(triangle_normal.clone()),
+                        },
+                        uv,
+                        tangent: tangent.clone(),
+                        bitangent: bitangent.clone(),
+                    });
+                    match object.shading {
+                        Shading::Flat => {
+                            let center = &(&(p1 + p2) + p3) * (1.0 / 3.0);
+                            let color = lighting.shade(material, &center, &triangle_normal, &WHITE);
+                            varyings.iter_mut().for_each(|v| v.color = color);
+                        }
+                        Shading::Gouraud => {
+                            for v in varyings.iter_mut() {
+                                v.color = lighting.shade(material, &v.world_position, &v.world_normal, &WHITE);
+                            }
+                        }
+                        Shading::Phong => {}
+                    }
+                    let triangle = Triangle::new(clip_positions[i1].clone(),
+                                                 clip_positions[i2].clone(),
+                                                 clip_positions[i3].clone());
+                    triangles.push(ShadedTriangle { triangle, varyings, object: object_ind, material: submesh.material });
+                }
             }
         }
 
-        let z_near_plane = Vec4::new_plane(Vec4::new3d(0.0, 0.0, self.z_near), Vec4::new3d(0.0, 0.0, 1.0));
-        triangles = clip_triangles(triangles, &z_near_plane);
-        let z_far_plane = Vec4::new_plane(Vec4::new3d(0.0, 0.0, self.z_far), Vec4::new3d(0.0, 0.0, -1.0));
-        triangles = clip_triangles(triangles, &z_far_plane);
+        // transparent triangles blend over whatever is drawn before them, so they go last
+        let material_of = |tr: &ShadedTriangle| &objects[tr.object].1.materials[tr.material];
+        triangles.sort_by_key(|tr| material_of(tr).opacity < 1.0);
 
-        let half_horizontal_fov = ((self.vertical_fov / 2.0).to_radians().tan() * aspect_ratio).atan().to_degrees();
-        let hor_normal_rotation_angle = 90.0 - half_horizontal_fov;
-        let ver_normal_rotation_angle = 90.0 - self.vertical_fov / 2.0;
+        let mut projected = Vec::with_capacity(triangles.len());
+        let mut polygon = Vec::with_capacity(9);
+        let mut scratch = Vec::with_capacity(9);
+        for ShadedTriangle { triangle: tr, varyings, object, material } in triangles {
+            polygon.clear();
+            for (position, varyings) in [tr.p1, tr.p2, tr.p3].into_iter().zip(varyings) {
+                polygon.push(ClipVertex { position, varyings });
+            }
+            clip_polygon(&mut polygon, &mut scratch);
 
-        let normal = &Mat4x4::rotation(&Vec3::new(0.0, hor_normal_rotation_angle, 0.0)) * &Vec4::new3d(0.0, 0.0, 1.0);
-        let left_plane = Vec4::new_plane(Vec4::new3d(0.0, 0.0, 0.0), normal);
-        triangles = clip_triangles(triangles, &left_plane);
-        let normal = &Mat4x4::rotation(&Vec3::new(0.0, -hor_normal_rotation_angle, 0.0)) * &Vec4::new3d(0.0, 0.0, 1.0);
-        let right_plane = Vec4::new_plane(Vec4::new3d(0.0, 0.0, 0.0), normal);
-        triangles = clip_triangles(triangles, &right_plane);
-        let normal = &Mat4x4::rotation(&Vec3::new(-ver_normal_rotation_angle, 0.0, 0.0)) * &Vec4::new3d(0.0, 0.0, 1.0);
-        let bot_plane = Vec4::new_plane(Vec4::new3d(0.0, 0.0, 0.0), normal);
-        triangles = clip_triangles(triangles, &bot_plane);
-        let normal = &Mat4x4::rotation(&Vec3::new(ver_normal_rotation_angle, 0.0, 0.0)) * &Vec4::new3d(0.0, 0.0, 1.0);
-        let top_plane = Vec4::new_plane(Vec4::new3d(0.0, 0.0, 0.0), normal);
-        triangles = clip_triangles(triangles, &top_plane);
+            // the clipped polygon is convex, so a fan from its first vertex covers it
+            for i in 1..polygon.len().saturating_sub(1) {
+                let corners = [&polygon[0], &polygon[i], &polygon[i + 1]];
+                let [p1, p2, p3] = corners.map(|corner| {
+                    let mut projected = corner.position.clone();
+                    projected.perspective_div();
+                    projected
+                });
+                let triangle = Triangle::new(p1, p2, p3);
+                let varyings = corners.map(|corner| corner.varyings.clone());
+                projected.push(ShadedTriangle { triangle, varyings, object, material });
+            }
+        }
+
+        let objects: Vec<&GameObject> = objects.into_iter().map(|(_, object)| object).collect();
+        let draw = |buffer: &mut DepthBuffer, tr: &ShadedTriangle| draw_triangle(buffer, tr, &objects, &lighting);
+        if thread_count <= 1 {
+            projected.iter().for_each(|tr| draw(buffer, tr));
+        } else {
+            draw_tiled(buffer, &projected, thread_count, draw);
+        }
+        *previous_lighting = Some(lighting);
+        return stats;
+    }
+
+    /// Splits the view between the near and far planes into `count` slices, each given by the corners of
+    /// its near and far rectangles in camera space and by the distances where it starts blending into
+    /// the next slice and where it ends. Slices grow with the distance, and each one overlaps the next
+    /// over its blend range.
+    fn cascade_slices(&self, count: usize, aspect_ratio: f32) -> Vec<([Vec4; 8], f32, f32)> {
+        let split = |i: usize| {
+            let fraction = i as f32 / count as f32;
+            let logarithmic = self.z_near * (self.z_far / self.z_near).powf(fraction);
+            let uniform = self.z_near + (self.z_far - self.z_near) * fraction;
+            return CASCADE_SPLIT_BLEND * logarithmic + (1.0 - CASCADE_SPLIT_BLEND) * uniform;
+        };
+        let tan_half_fov = (self.vertical_fov.to_radians() / 2.0).tan();
+        let rectangle = |distance: f32| {
+            let (half_width, half_height) = (distance * tan_half_fov * aspect_ratio, distance * tan_half_fov);
+            return [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+                .map(|(x, y)| Vec4::new3d(x * half_width, y * half_height, distance));
+        };
+        let mut slice_start = self.z_near;
+        return (0..count).map(|i| {
+            let z_far = split(i + 1);
+            let blend_start = z_far - (z_far - split(i)) * CASCADE_BLEND_FRACTION;
+            let ([a, b, c, d], [e, f, g, h]) = (rectangle(slice_start), rectangle(z_far));
+            slice_start = blend_start;
+            ([a, b, c, d, e, f, g, h], blend_start, z_far)
+        }).collect();
+    }
+
+    fn perspective_mat(&self, aspect_ratio: f32) -> Mat4x4 {
+        return Mat4x4::perspective(self.vertical_fov, aspect_ratio, self.z_near, self.z_far);
+    }
+
+    /// View matrix of a camera placed by `camera_mat`, ignoring any scale it has.
+    fn view_mat(camera_mat: &Mat4x4) -> Mat4x4 {
+        let to_vec3 = |vec: Vec4| Vec3::new(vec.x, vec.y, vec.z);
+        let eye = to_vec3(camera_mat * &Vec4::new3d(0.0, 0.0, 0.0));
+        let forward = to_vec3(camera_mat * &Vec4::new(0.0, 0.0, 1.0, 0.0));
+        let up = to_vec3(camera_mat * &Vec4::new(0.0, 1.0, 0.0, 0.0));
+        return Mat4x4::look_at(&eye, &(&eye + &forward), &up);
+    }
+}
+
+/// World-space derivatives of the position along u and v of a triangle with vertices `positions`
+/// and texture coordinates `uvs`, or zero vectors if the uvs are degenerate.
+fn uv_tangents(positions: [&Vec4; 3], uvs: &[Vec2<f32>; 3]) -> [Vec4; 2] {
+    let (edge1, edge2) = (positions[1] - positions[0], positions[2] - positions[0]);
+    let (uv_edge1, uv_edge2) = (&uvs[1] - &uvs[0], &uvs[2] - &uvs[0]);
+    let determinant = uv_edge1.x * uv_edge2.y - uv_edge2.x * uv_edge1.y;
+    if determinant.abs() < 1e-12 {
+        return [Vec4::default(), Vec4::default()];
+    }
+    let mut tangent = &(&(&edge1 * uv_edge2.y) - &(&edge2 * uv_edge1.y)) * (1.0 / determinant);
+    let mut bitangent = &(&(&edge2 * uv_edge1.x) - &(&edge1 * uv_edge2.x)) * (1.0 / determinant);
+    tangent.w = 0.0;
+    bitangent.w = 0.0;
+    return [tangent, bitangent];
+}
+
+/// Tilts `normal` against the slope of the height map at the fragment, measured over one texel.
+fn bump_normal(normal: &Vec4, varyings: &Varyings, bump_texture: &Texture, multiplier: f32) -> Vec4 {
+    if varyings.tangent.len() == 0.0 || varyings.bitangent.len() == 0.0 {
+        return normal.clone();
+    }
+    let (width, height) = bump_texture.size();
+    let zero = Vec2::new(0.0, 0.0);
+    let height_at = |uv: Vec2<f32>| bump_texture.sample(&uv, &zero, &zero).x;
+    let uv = varyings.uv;
+    let center = height_at(uv);
+    let slope_u = height_at(Vec2::new(uv.x + 1.0 / width as f32, uv.y)) - center;
+    let slope_v = height_at(Vec2::new(uv.x, uv.y + 1.0 / height as f32)) - center;
 
-        let light_direction = &(&Mat4x4::rotation(&scene.directional_light_rotation) * &Vec4::new3d(0.0, 0.0, 1.0));
+    let offset = &(&varyings.tangent.normalized() * slope_u) + &(&varyings.bitangent.normalized() * slope_v);
+    let mut bumped = (normal - &(&offset * multiplier)).normalized();
+    bumped.w = 0.0;
+    return bumped;
+}
 
-        for tr in triangles {
-            let alpha = light_direction.dot(&tr.world_normal.unwrap());
-            let color = BLACK_COLOR.lerp(&MODEL_COLOR, alpha);
+/// Renders the scene, rasterizing screen tiles on `thread_count` threads when it is more than one.
+/// The image is the same for any number of threads.
+pub fn render(buffer: &mut DepthBuffer, scene: &Scene, thread_count: usize) -> RenderStats {
+    return render_reusing_shadow_maps(buffer, scene, thread_count, &mut None);
+}
 
-            let mut projected_p1 = &perspective_mat * &tr.p1;
-            projected_p1.perspective_div();
-            let mut projected_p2 = &perspective_mat * &tr.p2;
-            projected_p2.perspective_div();
-            let mut projected_p3 = &perspective_mat * &tr.p3;
-            projected_p3.perspective_div();
-            let projected_tr = Triangle::new(projected_p1, projected_p2, projected_p3);
+/// Renders the scene like `render`, drawing the shadow maps into the depth buffers of `lighting` from
+/// the previous frame, and leaves the lighting of this frame in it for the next one.
+pub fn render_reusing_shadow_maps(buffer: &mut DepthBuffer, scene: &Scene, thread_count: usize,
+                                  lighting: &mut Option<SceneLighting>) -> RenderStats {
+    buffer.clear(BACKGROUND_COLOR);
+    return scene.camera.render(buffer, scene, thread_count, lighting);
+}
 
-            rasterize_triangle(buffer, &projected_tr, color);
-            draw_wireframe_triangle(buffer, &projected_tr);
+/// Renders the depth of every object as seen from a light placed by `light_mat` into a shadow map, for a
+/// camera rendering a screen of `aspect_ratio`. Spot lights use a perspective projection over their cone
+/// fitted around the objects. Directional lights use a parallel one, fitted around the objects or, with
+/// several cascades, around the slices of the camera's view. The depth buffers of `previous` are drawn
+/// over again if they have the resolution of `shadow`. Returns None for point lights and if no object is
+/// in front of the light.
+pub fn render_shadow_map(scene: &Scene, world_mats: &[Mat4x4], aspect_ratio: f32, light_mat: &Mat4x4, light: &Light,
+                         shadow: Shadow, previous: Option<ShadowMap>) -> Option<ShadowMap> {
+    let view_mat = match light {
+        // only the direction of a directional light matters, and keeping its position out of the view
+        // matrix keeps the texel grid in place when the light node moves
+        Light::Directional { .. } => {
+            let mut rotation_mat = Mat4x4 { content: light_mat.content };
+            (0..3).for_each(|row| rotation_mat.content[row][3] = 0.0);
+            Camera::view_mat(&rotation_mat)
         }
+        Light::Spot { .. } => Camera::view_mat(light_mat),
+        Light::Point { .. } => return None,
+    };
+    let view_positions: Vec<(&GameObject, Vec<Vec4>)> = scene.objects().map(|(node, object)| {
+        let mut positions: Vec<Vec4> = object.mesh.vertices.iter().map(|vertex| vertex.position.clone()).collect();
+        (&view_mat * &world_mats[node]).transform_all(&mut positions);
+        (object, positions)
+    }).collect();
+    let (mut min, mut max) = (Vec3::new(f32::MAX, f32::MAX, f32::MAX), Vec3::new(f32::MIN, f32::MIN, f32::MIN));
+    for position in view_positions.iter().flat_map(|(_, positions)| positions) {
+        min = Vec3::new(min.x.min(position.x), min.y.min(position.y), min.z.min(position.z));
+        max = Vec3::new(max.x.max(position.x), max.y.max(position.y), max.z.max(position.z));
     }
+    if min.x > max.x {
+        return None;
+    }
+    // a little room around the objects keeps their outermost surfaces inside the map
+    let pad = |min: f32, max: f32| {
+        let margin = (max - min) * 0.01 + 1e-4;
+        (min - margin, max + margin)
+    };
+    let camera_view_mat = Camera::view_mat(&world_mats[scene.camera_node]);
+    let size = ScreenSize { width: shadow.resolution, height: shadow.resolution };
+    let mut spare_depths: Vec<DepthBuffer> = previous.into_iter()
+        .flat_map(|shadow_map| shadow_map.cascades)
+        .map(|cascade| cascade.depth)
+        .filter(|depth| depth.screen_size == size)
+        .collect();
+    let mut render_depth = |projection: &Mat4x4| {
+        let mut depth = spare_depths.pop().unwrap_or_else(|| DepthBuffer::new(size));
+        render_shadow_depth(&mut depth, projection, &view_positions);
+        return depth;
+    };
+    let mut single = |projection: Mat4x4| {
+        let depth = render_depth(&projection);
+        let clip_mat = &projection * &view_mat;
+        ShadowCascade { clip_mat, depth, blend_start: f32::MAX, z_far: f32::MAX, bias: shadow.bias }
+    };
+    let cascades = match *light {
+        Light::Directional { .. } if shadow.cascades > 1 => {
+            let (z_near, _) = pad(min.z, max.z);
+            let to_light = &view_mat * &camera_view_mat.inverse()?;
+            let mut cascades = Vec::with_capacity(shadow.cascades);
+            let mut first_scale = None;
+            for (corners, blend_start, z_far) in scene.camera.cascade_slices(shadow.cascades, aspect_ratio) {
+                let projection = fit_cascade(&to_light, &corners, z_near, shadow.resolution);
+                let depth = render_depth(&projection);
+                // the bias is given for the first cascade and grows with the texel size of the others
+                let scale = projection.content[0][0];
+                let bias = shadow.bias * *first_scale.get_or_insert(scale) / scale;
+                cascades.push(ShadowCascade { clip_mat: &projection * &view_mat, depth, blend_start, z_far, bias });
+            }
+            cascades
+        }
+        Light::Directional { .. } => {
+            let ((left, right), (bottom, top), (z_near, z_far)) = (pad(min.x, max.x), pad(min.y, max.y), pad(min.z, max.z));
+            vec![single(Mat4x4::orthographic(left, right, bottom, top, z_near, z_far))]
+        }
+        Light::Spot { outer_angle, .. } => {
+            if max.z <= 0.0 {
+                return None;
+            }
+            let z_far = max.z * 1.01;
+            let z_near = (min.z * 0.99).max(z_far * 0.001);
+            vec![single(Mat4x4::perspective((2.0 * outer_angle).min(170.0), 1.0, z_near, z_far))]
+        }
+        Light::Point { .. } => return None,
+    };
+    return Some(ShadowMap { shadow, cascades, camera_view_mat });
+}
 
-    fn perspective_mat(&self, aspect_ratio: f32) -> Mat4x4 {
-        let mut res = Mat4x4::default();
-        let half_vertical_fov = self.vertical_fov.to_radians() / 2.0;
-        res.content[0][0] = 1.0 / (half_vertical_fov.tan() * aspect_ratio);
-        res.content[1][1] = 1.0 / (half_vertical_fov.tan());
-        res.content[2][2] = self.z_far / (self.z_far - self.z_near);
-        res.content[2][3] = -self.z_far * self.z_near / (self.z_far - self.z_near);
-        res.content[3][2] = 1.0;
-        return res;
-    }
-}
-
-pub fn render(screen_size: ScreenSize, scene: &Scene) -> Vec<Color> {
-    let mut buffer = DepthBuffer::new(screen_size, BACKGROUND_COLOR);
-    scene.camera.render(&mut buffer, scene);
-
-    return buffer.to_bitmap();
-}
-
-fn clip_triangles(triangles: Vec<Triangle>, plane: &impl Plane) -> Vec<Triangle> {
-    let mut clipped = vec![];
-    for tr in triangles {
-        let mut res1 = None;
-        let mut res2 = None;
-        clip_triangle(tr, plane, &mut res1, &mut res2);
-        if let Some(tr1) = res1 {
-            clipped.push(tr1);
-            if let Some(tr2) = res2 {
-                clipped.push(tr2);
+/// Parallel projection of a directional light's view space containing the bounding sphere of a cascade's
+/// `corners` given in camera space, with the shadow casters from `z_near` on. The sphere's size does not
+/// change as the camera turns, and its center is snapped to whole texels, so that the shadow edges stay
+/// in place while the camera moves.
+fn fit_cascade(to_light: &Mat4x4, corners: &[Vec4; 8], z_near: f32, resolution: i32) -> Mat4x4 {
+    let center = &corners.iter().fold(Vec4::default(), |sum, corner| &sum + corner) * (1.0 / 8.0);
+    let radius = corners.iter().map(|corner| (corner - &center).len()).fold(0.0, f32::max);
+    // rounded up so that floating point noise does not change the size either
+    let radius = (radius * 64.0).ceil() / 64.0;
+    let texel_size = 2.0 * radius / resolution as f32;
+    let mut center = to_light * &Vec4::new3d(center.x, center.y, center.z);
+    center.x = (center.x / texel_size).floor() * texel_size;
+    center.y = (center.y / texel_size).floor() * texel_size;
+    let z_near = z_near.min(center.z - radius);
+    return Mat4x4::orthographic(center.x - radius, center.x + radius, center.y - radius, center.y + radius,
+                                z_near, center.z + radius);
+}
+
+/// Clears the map and rasterizes into it the depth of the objects, whose vertices are in the light's view
+/// space, with `projection`.
+fn render_shadow_depth(depth: &mut DepthBuffer, projection: &Mat4x4, view_positions: &[(&GameObject, Vec<Vec4>)]) {
+    depth.clear(BACKGROUND_COLOR);
+    let size = depth.screen_size;
+    let no_varyings: [Varyings; 3] = Default::default();
+    let mut polygon = Vec::with_capacity(9);
+    let mut scratch = Vec::with_capacity(9);
+    for (object, positions) in view_positions {
+        let mut positions = positions.clone();
+        projection.transform_all(&mut positions);
+        for triangle in object.mesh.triangles() {
+            polygon.clear();
+            polygon.extend(triangle.map(|i| ClipVertex { position: positions[i].clone(), varyings: Varyings::default() }));
+            clip_polygon(&mut polygon, &mut scratch);
+            for i in 1..polygon.len().saturating_sub(1) {
+                let [p1, p2, p3] = [0, i, i + 1].map(|corner| {
+                    let mut projected = polygon[corner].position.clone();
+                    projected.perspective_div();
+                    projected
+                });
+                rasterize_triangle(size, size.rect(), &Triangle::new(p1, p2, p3), &no_varyings, |x, y, z, _| {
+                    if depth.passes_depth_test(x, y, z) {
+                        depth.set_pixel(x, y, DeepPixel { color: BACKGROUND_COLOR, depth: z });
+                    }
+                });
             }
         }
     }
-    return clipped;
 }
 
-fn clip_triangle(triangle: Triangle, plane: &impl Plane,
-                 res1: &mut Option<Triangle>, res2: &mut Option<Triangle>) {
-    let mut inside_count = 0;
-    let mut outside_count = 0;
-    let mut inside_points = [&triangle.p1; 3];
-    let mut outside_points = [&triangle.p1; 3];
-    for point in [&triangle.p1, &triangle.p2, &triangle.p3] {
-        if plane.is_point_inside(&point) {
-            inside_points[inside_count] = point;
-            inside_count += 1;
-        } else {
-            outside_points[outside_count] = point;
-            outside_count += 1;
+/// Rasterizes and shades one projected triangle together with its wireframe.
+fn draw_triangle(buffer: &mut DepthBuffer, tr: &ShadedTriangle, objects: &[&GameObject], lighting: &SceneLighting) {
+    let object = objects[tr.object];
+    let material = &object.materials[tr.material];
+    let shade_pixel = |fragment: &Fragment| {
+        let v = &fragment.varyings;
+        let texture_color = material.diffuse_texture.as_ref()
+            .map_or(WHITE, |texture| texture.sample(&v.uv, &fragment.uv_dx, &fragment.uv_dy));
+        match object.shading {
+            Shading::Flat | Shading::Gouraud => to_color(&v.color.mul_elements(&texture_color)),
+            Shading::Phong => {
+                let mut normal = v.world_normal.normalized();
+                normal.w = 0.0;
+                if let Some(bump_texture) = &material.bump_texture {
+                    normal = bump_normal(&normal, v, bump_texture, material.bump_multiplier);
+                }
+                to_color(&lighting.shade(material, &v.world_position, &normal, &texture_color))
+            }
+        }
+    };
+    rasterize_triangle(buffer.screen_size, buffer.region, &tr.triangle, &tr.varyings, |x, y, depth, fragment| {
+        // pushed slightly back so that the wireframe drawn at the exact depth stays in front
+        let depth = depth + 0.01 * (1.0 - depth) + 0.000001;
+        if buffer.passes_depth_test(x, y, depth) {
+            let pixel = DeepPixel { color: shade_pixel(&fragment), depth };
+            buffer.blend_pixel(x, y, pixel, material.opacity);
         }
+    });
+    if object.wireframe {
+        draw_wireframe_triangle(buffer, &tr.triangle);
     }
+}
 
-    if inside_count == 0 {
-        return;
-    } else if inside_count == 3 {
-        *res1 = Some(triangle);
-    } else if inside_count == 1 {
-        let intersection1 = plane.intersect_with_segment(&inside_points[0], &outside_points[0]);
-        let intersection2 = plane.intersect_with_segment(&inside_points[0], &outside_points[1]);
-        *res1 = Some(Triangle::new_with_normal(inside_points[0].clone(), intersection1, intersection2, triangle.world_normal));
-    } else { // if inside_points == 2
-        let intersection1 = plane.intersect_with_segment(&inside_points[0], &outside_points[0]);
-        let intersection2 = plane.intersect_with_segment(&inside_points[1], &outside_points[0]);
-        *res1 = Some(Triangle::new_with_normal(inside_points[0].clone(), inside_points[1].clone(), intersection1.clone(), triangle.world_normal.clone()));
-        *res2 = Some(Triangle::new_with_normal(inside_points[1].clone(), intersection2, intersection1, triangle.world_normal.clone()));
-    }
-}
-
-fn rasterize_triangle(buffer: &mut DepthBuffer, tr: &Triangle, color: Color) {
-    let tr = tr.clockwise();
-    let p1 = tr.p1;
-    let p2 = tr.p2;
-    let p3 = tr.p3;
-
-    let x_min = p1.x.min(p2.x).min(p3.x);
-    let x_max = p1.x.max(p2.x).max(p3.x);
-    let y_min = p1.y.min(p2.y).min(p3.y);
-    let y_max = p1.y.max(p2.y).max(p3.y);
-
-    let pixel_bot_left = buffer.screen_space_to_pixel_pos(x_min, y_min);
-    let pixel_top_right = buffer.screen_space_to_pixel_pos(x_max, y_max);
-
-    let tr_area = (&p2 - &p1).cross_len_2d(&(&p3 - &p2));
-
-    let x_delta = (x_max - x_min) / (pixel_top_right.x - pixel_bot_left.x) as f32;
-    let y_delta = (y_max - y_min) / (pixel_top_right.y - pixel_bot_left.y) as f32;
-    let mut x = x_min;
-    let mut y = y_min;
-    for _y in pixel_bot_left.y..=pixel_top_right.y {
-        for _x in pixel_bot_left.x..=pixel_top_right.x {
-            let point = Vec4::new3d(x, y, 0.0);
-
-            let t3 = (&p2 - &p1).cross_len_2d(&(&point - &p1)) / tr_area;
-            let t1 = (&p3 - &p2).cross_len_2d(&(&point - &p2)) / tr_area;
-            let t2 = (&p1 - &p3).cross_len_2d(&(&point - &p3)) / tr_area;
-
-            if t1 >= 0.0 && t2 >= 0.0 && t3 >= 0.0 {
-                let mut z = (t1 * p1.z) + (t2 * p2.z) + (t3 * p3.z);
-                z += 0.01 * (1.0 - z) + 0.000001;
-
-                let pixel = DeepPixel { color, depth: z };
-                buffer.set_screen_space_pixel(x, y, pixel);
+/// Splits the screen into tiles, bins the projected triangles by the tiles they may touch, and
+/// draws the tiles on `thread_count` threads. Every tile draws its triangles in the original order,
+/// so each pixel goes through the same writes as when drawing the whole screen at once.
+fn draw_tiled(buffer: &mut DepthBuffer, triangles: &[ShadedTriangle], thread_count: usize,
+              draw: impl Fn(&mut DepthBuffer, &ShadedTriangle) + Sync) {
+    let screen_size = buffer.screen_size;
+    let columns = (screen_size.width + TILE_SIZE - 1) / TILE_SIZE;
+    let rows = (screen_size.height + TILE_SIZE - 1) / TILE_SIZE;
+    let tile_rect = |tile: usize| {
+        let (column, row) = (tile as i32 % columns, tile as i32 / columns);
+        let rect = PixelRect {
+            x_min: column * TILE_SIZE,
+            y_min: row * TILE_SIZE,
+            x_max: (column + 1) * TILE_SIZE,
+            y_max: (row + 1) * TILE_SIZE,
+        };
+        return rect.intersection(&screen_size.rect());
+    };
+
+    let mut bins: Vec<Vec<usize>> = vec![vec![]; (columns * rows) as usize];
+    for (ind, tr) in triangles.iter().enumerate() {
+        let bounds = pixel_bounds(&tr.triangle, screen_size).intersection(&screen_size.rect());
+        if bounds.x_min >= bounds.x_max || bounds.y_min >= bounds.y_max {
+            continue;
+        }
+        for row in bounds.y_min / TILE_SIZE..=(bounds.y_max - 1) / TILE_SIZE {
+            for column in bounds.x_min / TILE_SIZE..=(bounds.x_max - 1) / TILE_SIZE {
+                bins[(row * columns + column) as usize].push(ind);
             }
+        }
+    }
 
-            x += x_delta;
+    // workers take the next undrawn tile until none are left, so uneven tiles balance out
+    let next_tile = AtomicUsize::new(0);
+    let drawn_tiles = Mutex::new(Vec::with_capacity(bins.len()));
+    thread::scope(|scope| {
+        for _ in 0..thread_count.min(bins.len()) {
+            scope.spawn(|| loop {
+                let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+                if tile >= bins.len() {
+                    return;
+                }
+                let mut tile_buffer = DepthBuffer::new_region(screen_size, tile_rect(tile));
+                for &ind in &bins[tile] {
+                    draw(&mut tile_buffer, &triangles[ind]);
+                }
+                drawn_tiles.lock().unwrap().push(tile_buffer);
+            });
         }
-        y += y_delta;
-        x = x_min;
+    });
+    for tile_buffer in drawn_tiles.into_inner().unwrap() {
+        buffer.copy_tile(&tile_buffer);
+    }
+}
+
+/// Pixels a projected triangle and its wireframe may write to.
+fn pixel_bounds(tr: &Triangle, screen_size: ScreenSize) -> PixelRect {
+    let to_pixels = |ndc: f32, size: i32| (ndc + 1.0) / 2.0 * size as f32;
+    let xs = [&tr.p1, &tr.p2, &tr.p3].map(|p| to_pixels(p.x, screen_size.width));
+    let ys = [&tr.p1, &tr.p2, &tr.p3].map(|p| to_pixels(p.y, screen_size.height));
+    // wireframe points reach beyond the triangle, and their centers are rounded to whole pixels
+    let margin = WIREFRAME_POINT_RADIUS + 1;
+    return PixelRect {
+        x_min: xs.iter().fold(f32::MAX, |a, &b| a.min(b)).floor() as i32 - margin,
+        y_min: ys.iter().fold(f32::MAX, |a, &b| a.min(b)).floor() as i32 - margin,
+        x_max: xs.iter().fold(f32::MIN, |a, &b| a.max(b)).ceil() as i32 + margin + 1,
+        y_max: ys.iter().fold(f32::MIN, |a, &b| a.max(b)).ceil() as i32 + margin + 1,
+    };
+}
+
+/// Whether a triangle at `point` with the world-space `normal` of its vertex order is skipped.
+/// With counter-clockwise front faces the normal points out of the front side.
+fn is_culled(normal: &Vec4, point: &Vec4, camera_position: &Vec4, cull_mode: CullMode, front_face: FrontFace) -> bool {
+    let towards_normal = normal.dot(&(camera_position - point)) > 0.0;
+    let is_front = match front_face {
+        FrontFace::CounterClockwise => towards_normal,
+        FrontFace::Clockwise => !towards_normal,
+    };
+    return match cull_mode {
+        CullMode::None => false,
+        CullMode::Back => !is_front,
+        CullMode::Front => is_front,
+    };
+}
+
+impl Lerp<Varyings> for Varyings {
+    fn lerp(&self, rhs: &Varyings, alpha: f32) -> Varyings {
+        Varyings {
+            color: self.color.lerp(&rhs.color, alpha),
+            world_position: self.world_position.lerp(&rhs.world_position, alpha),
+            world_normal: self.world_normal.lerp(&rhs.world_normal, alpha),
+            uv: self.uv.lerp(&rhs.uv, alpha),
+            tangent: self.tangent.lerp(&rhs.tangent, alpha),
+            bitangent: self.bitangent.lerp(&rhs.bitangent, alpha),
+        }
+    }
+}
+
+impl Varyings {
+    /// Blends the varyings of a triangle's vertices with barycentric weights.
+    fn interpolate(varyings: &[Varyings; 3], t1: f32, t2: f32, t3: f32) -> Varyings {
+        let [v1, v2, v3] = varyings;
+        Varyings {
+            color: &(&(&v1.color * t1) + &(&v2.color * t2)) + &(&v3.color * t3),
+            world_position: &(&(&v1.world_position * t1) + &(&v2.world_position * t2)) + &(&v3.world_position * t3),
+            world_normal: &(&(&v1.world_normal * t1) + &(&v2.world_normal * t2)) + &(&v3.world_normal * t3),
+            uv: Vec2::new(t1 * v1.uv.x + t2 * v2.uv.x + t3 * v3.uv.x,
+                          t1 * v1.uv.y + t2 * v2.uv.y + t3 * v3.uv.y),
+            // constant across the triangle
+            tangent: v1.tangent.clone(),
+            bitangent: v1.bitangent.clone(),
+        }
+    }
+}
+
+/// Signed distances of a clip-space point to the planes bounding the view volume, each one
+/// non-negative inside: -w <= x <= w, -w <= y <= w, and 0 <= z <= w as produced by `perspective_mat`.
+const CLIP_PLANES: [fn(&Vec4) -> f32; 6] = [
+    |p| p.w + p.x,
+    |p| p.w - p.x,
+    |p| p.w + p.y,
+    |p| p.w - p.y,
+    |p| p.z,
+    |p| p.w - p.z,
+];
+
+/// Clips the convex polygon against every plane of the view volume in turn (Sutherland-Hodgman),
+/// interpolating the varyings of the new vertices. `scratch` holds the intermediate polygons so
+/// clipping does not allocate once both vectors have grown.
+fn clip_polygon(polygon: &mut Vec<ClipVertex>, scratch: &mut Vec<ClipVertex>) {
+    for distance in CLIP_PLANES {
+        if polygon.iter().all(|vertex| distance(&vertex.position) >= 0.0) {
+            continue;
+        }
+        scratch.clear();
+        for (ind, current) in polygon.iter().enumerate() {
+            let next = &polygon[(ind + 1) % polygon.len()];
+            let (current_distance, next_distance) = (distance(&current.position), distance(&next.position));
+            if current_distance >= 0.0 {
+                scratch.push(current.clone());
+            }
+            if (current_distance >= 0.0) != (next_distance >= 0.0) {
+                let t = current_distance / (current_distance - next_distance);
+                scratch.push(ClipVertex {
+                    position: current.position.lerp(&next.position, t),
+                    varyings: current.varyings.lerp(&next.varyings, t),
+                });
+            }
+        }
+        swap(polygon, scratch);
+    }
+}
+
+/// Edge function of the edge from `a` to `b`: twice the signed area of the triangle it forms with `p`,
+/// positive when `p` lies to the left of the edge.
+fn edge_function(a: (i64, i64), b: (i64, i64), p: (i64, i64)) -> i64 {
+    (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+}
+
+/// Calls `plot` with the pixel position, depth and fragment of every pixel whose center the triangle
+/// covers inside `region`, given in normalized device coordinates. Vertices are snapped to 1/`SUBPIXEL_SCALE` of a
+/// pixel, and a pixel center exactly on an edge is only covered if it is a top or left edge, so
+/// triangles sharing an edge cover every pixel along it exactly once.
+fn rasterize_triangle(screen_size: ScreenSize, region: PixelRect, tr: &Triangle, varyings: &[Varyings; 3],
+                      mut plot: impl FnMut(i32, i32, f32, Fragment)) {
+    let to_fixed = |p: &Vec4| {
+        let x = (p.x + 1.0) / 2.0 * screen_size.width as f32;
+        let y = (p.y + 1.0) / 2.0 * screen_size.height as f32;
+        ((x * SUBPIXEL_SCALE as f32).round() as i64, (y * SUBPIXEL_SCALE as f32).round() as i64)
+    };
+    // the edge functions below expect counter-clockwise vertices, so clockwise triangles are reordered
+    let mut order = [0, 1, 2];
+    let mut fixed = [&tr.p1, &tr.p2, &tr.p3].map(to_fixed);
+    let area = edge_function(fixed[0], fixed[1], fixed[2]);
+    if area == 0 {
+        return;
+    }
+    if area < 0 {
+        order.swap(1, 2);
+        fixed.swap(1, 2);
+    }
+    let area = area.abs() as f32;
+    let points = order.map(|i| [&tr.p1, &tr.p2, &tr.p3][i]);
+    let varyings = order.map(|i| varyings[i].clone());
+
+    // pixel centers sit at half-integer coordinates, and the range is clamped to the region
+    let half = SUBPIXEL_SCALE / 2;
+    let first_center = |min: i64, region_min: i32| {
+        (min - half + SUBPIXEL_SCALE - 1).div_euclid(SUBPIXEL_SCALE).max(region_min as i64)
+    };
+    let last_center = |max: i64, region_max: i32| (max - half).div_euclid(SUBPIXEL_SCALE).min(region_max as i64 - 1);
+    let x_first = first_center(fixed.iter().map(|p| p.0).min().unwrap(), region.x_min);
+    let x_last = last_center(fixed.iter().map(|p| p.0).max().unwrap(), region.x_max);
+    let y_first = first_center(fixed.iter().map(|p| p.1).min().unwrap(), region.y_min);
+    let y_last = last_center(fixed.iter().map(|p| p.1).max().unwrap(), region.y_max);
+    if x_first > x_last || y_first > y_last {
+        return;
+    }
+
+    // edge i is opposite vertex i, so its edge function is the barycentric weight of that vertex
+    let edges = [(fixed[1], fixed[2]), (fixed[2], fixed[0]), (fixed[0], fixed[1])];
+    // with counter-clockwise vertices and y up, left edges point down and top edges point left
+    let is_top_left = |a: (i64, i64), b: (i64, i64)| b.1 < a.1 || (b.1 == a.1 && b.0 < a.0);
+    let bias = edges.map(|(a, b)| if is_top_left(a, b) { 0 } else { -1 });
+    let x_step = edges.map(|(a, b)| -(b.1 - a.1) * SUBPIXEL_SCALE);
+    let y_step = edges.map(|(a, b)| (b.0 - a.0) * SUBPIXEL_SCALE);
+    let first_sample = (x_first * SUBPIXEL_SCALE + half, y_first * SUBPIXEL_SCALE + half);
+    let mut row_start = edges.map(|(a, b)| edge_function(a, b, first_sample));
+
+    // screen-space weights are affine in 1/w, so weighting each vertex by its 1/w (kept in
+    // `w` after the perspective divide) and renormalizing gives perspective-correct varyings
+    let perspective_weights = |e: [i64; 3]| {
+        let w = [0, 1, 2].map(|i| e[i] as f32 / area * points[i].w);
+        let w_sum = w[0] + w[1] + w[2];
+        return (w[0] / w_sum, w[1] / w_sum, w[2] / w_sum);
+    };
+    let uv_at = |(w1, w2, w3): (f32, f32, f32)| {
+        Vec2::new(w1 * varyings[0].uv.x + w2 * varyings[1].uv.x + w3 * varyings[2].uv.x,
+                  w1 * varyings[0].uv.y + w2 * varyings[1].uv.y + w3 * varyings[2].uv.y)
+    };
+
+    for y in y_first..=y_last {
+        let mut e = row_start;
+        for x in x_first..=x_last {
+            if e[0] + bias[0] >= 0 && e[1] + bias[1] >= 0 && e[2] + bias[2] >= 0 {
+                let t = e.map(|e| e as f32 / area);
+                let z = t[0] * points[0].z + t[1] * points[1].z + t[2] * points[2].z;
+
+                let (w1, w2, w3) = perspective_weights(e);
+                let interpolated = Varyings::interpolate(&varyings, w1, w2, w3);
+                let next_x = [0, 1, 2].map(|i| e[i] + x_step[i]);
+                let next_y = [0, 1, 2].map(|i| e[i] + y_step[i]);
+                let fragment = Fragment {
+                    uv_dx: &uv_at(perspective_weights(next_x)) - &interpolated.uv,
+                    uv_dy: &uv_at(perspective_weights(next_y)) - &interpolated.uv,
+                    varyings: interpolated,
+                };
+                plot(x as i32, y as i32, z, fragment);
+            }
+            e = [0, 1, 2].map(|i| e[i] + x_step[i]);
+        }
+        row_start = [0, 1, 2].map(|i| row_start[i] + y_step[i]);
     }
 }
 
@@ -344,11 +966,10 @@ fn rasterize_wireframe_point(buffer: &mut DepthBuffer,
     };
 
     let pixel_pos = buffer.screen_space_to_pixel_pos(p.x, p.y);
-    let point_radius = 3;
-    let left = max(0, pixel_pos.x - point_radius);
-    let right = min(buffer.screen_size.width, pixel_pos.x + point_radius);
-    let bot = max(0, pixel_pos.y - point_radius);
-    let top = min(buffer.screen_size.height, pixel_pos.y + point_radius);
+    let left = max(0, pixel_pos.x - WIREFRAME_POINT_RADIUS);
+    let right = min(buffer.screen_size.width, pixel_pos.x + WIREFRAME_POINT_RADIUS);
+    let bot = max(0, pixel_pos.y - WIREFRAME_POINT_RADIUS);
+    let top = min(buffer.screen_size.height, pixel_pos.y + WIREFRAME_POINT_RADIUS);
 
     for screen_x in left..=right {
         for screen_y in bot..=top {
@@ -357,3 +978,549 @@ fn rasterize_wireframe_point(buffer: &mut DepthBuffer,
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::cube;
+    use crate::lighting::Material;
+    use crate::lighting::SceneLighting;
+    use crate::math::{Mesh, Quat, Transform, Vertex};
+    use crate::texture::{Filter, Texture, Wrap};
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    fn checker(u: f32, v: f32) -> Color {
+        let value = if ((u * 2.0).floor() + (v * 2.0).floor()) as i32 % 2 == 0 { 255 } else { 0 };
+        return Color { red: value, green: value, blue: value, alpha: 0 };
+    }
+
+    #[test]
+    fn checkerboard_floor_is_perspective_correct() {
+        let screen_size = ScreenSize { width: 160, height: 120 };
+        let aspect_ratio = screen_size.width as f32 / screen_size.height as f32;
+        let camera = Camera {
+            vertical_fov: 60.0,
+            z_near: 0.1,
+            z_far: 10.0,
+        };
+        let perspective_mat = camera.perspective_mat(aspect_ratio);
+
+        // floor at y = -0.5 spanning x in -2..2 and z in 0.5..4.5, with uv equal to world x and z
+        let floor_y = -0.5;
+        let corner = |x: f32, z: f32| {
+            let mut projected = &perspective_mat * &Vec4::new3d(x, floor_y, z);
+            projected.perspective_div();
+            let varyings = Varyings { uv: Vec2::new(x, z), ..Varyings::default() };
+            (projected, varyings)
+        };
+        let mut buffer = DepthBuffer::new(screen_size);
+        for [a, b, c] in [[(-2.0, 0.5), (-2.0, 4.5), (2.0, 4.5)], [(-2.0, 0.5), (2.0, 4.5), (2.0, 0.5)]] {
+            let [(p1, v1), (p2, v2), (p3, v3)] = [a, b, c].map(|(x, z)| corner(x, z));
+            let tr = Triangle::new(p1, p2, p3);
+            rasterize_triangle(screen_size, screen_size.rect(), &tr, &[v1, v2, v3], |x, y, depth, f| {
+                buffer.set_pixel(x, y, DeepPixel { color: checker(f.varyings.uv.x, f.varyings.uv.y), depth });
+            });
+        }
+
+        // cast rays through every pixel center and look up the checker where they hit the floor
+        let tan_half_fov = (camera.vertical_fov / 2.0).to_radians().tan();
+        let floor_uv = |pixel_x: f32, pixel_y: f32| {
+            let screen_x = pixel_x / screen_size.width as f32 * 2.0 - 1.0;
+            let screen_y = pixel_y / screen_size.height as f32 * 2.0 - 1.0;
+            let direction = Vec3::new(screen_x * tan_half_fov * aspect_ratio, screen_y * tan_half_fov, 1.0);
+            let t = floor_y / direction.y;
+            (direction.x * t, direction.z * t)
+        };
+        let mut compared = 0;
+        let mut mismatched = 0;
+        for pixel_y in 0..screen_size.height {
+            for pixel_x in 0..screen_size.width {
+                let (x, z) = floor_uv(pixel_x as f32 + 0.5, pixel_y as f32 + 0.5);
+                if pixel_y as f32 >= screen_size.height as f32 / 2.0 - 1.0 || x.abs() > 1.9 || !(0.6..4.4).contains(&z) {
+                    continue;
+                }
+                // centers right on a checker border may round either way
+                let border_distance = |value: f32| ((value * 2.0).round() - value * 2.0).abs();
+                if border_distance(x) < 1e-3 || border_distance(z) < 1e-3 {
+                    continue;
+                }
+
+                let row = screen_size.height - 1 - pixel_y;
+                let actual = buffer.buffer[(row * screen_size.width + pixel_x) as usize].color;
+                compared += 1;
+                if checker(x, z).red != actual.red {
+                    mismatched += 1;
+                }
+            }
+        }
+        assert!(compared > 2000);
+        assert_eq!(mismatched, 0, "{} of {} pixels differ", mismatched, compared);
+    }
+
+    #[test]
+    fn culling_skips_triangles_facing_away() {
+        // the camera looks at the front face of the cube head on and sees the four sides edge on
+        let mut scene = Scene::new(cube(), vec![Material::default()]);
+        let mut buffer = DepthBuffer::new(ScreenSize { width: 32, height: 24 });
+        let mut culled = |cull_mode: CullMode, front_face: FrontFace| {
+            let object = scene.object_mut(0).unwrap();
+            object.cull_mode = cull_mode;
+            object.front_face = front_face;
+            return render(&mut buffer, &scene, 1).culled_triangles;
+        };
+        assert_eq!(culled(CullMode::None, FrontFace::CounterClockwise), 0);
+        assert_eq!(culled(CullMode::Back, FrontFace::CounterClockwise), 10);
+        assert_eq!(culled(CullMode::Front, FrontFace::CounterClockwise), 2);
+        assert_eq!(culled(CullMode::Back, FrontFace::Clockwise), 2);
+        assert_eq!(culled(CullMode::Front, FrontFace::Clockwise), 10);
+    }
+
+    #[test]
+    fn tiled_rendering_matches_single_thread() {
+        // a rotated textured cube in front of a larger transparent one, on a screen that is not a
+        // whole number of tiles
+        let mut scene = Scene::new(cube(), vec![Material::default()]);
+        let pixels: Vec<Color> = (0..64).map(|i| checker((i % 8) as f32 / 8.0, (i / 8) as f32 / 8.0)).collect();
+        let texture = Arc::new(Texture::new(8, 8, &pixels, Filter::Trilinear, Wrap::Repeat));
+        let front = scene.object_mut(0).unwrap();
+        front.materials[0].diffuse_texture = Some(texture);
+        front.mesh.vertices.iter_mut().for_each(|v| v.position.z -= 2.0);
+        scene.node_mut(0).unwrap().transform = Transform {
+            translation: Vec3::new(0.0, 0.0, 1.0),
+            rotation: Quat::from_euler(&Vec3::new(25.0, 35.0, 0.0)),
+            ..Transform::default()
+        };
+        let mut back = GameObject::new(cube(), vec![Material { opacity: 0.5, ..Material::default() }]);
+        back.mesh.vertices.iter_mut().for_each(|v| v.position.z -= 2.0);
+        let transform = Transform {
+            translation: Vec3::new(0.8, 0.3, 2.5),
+            rotation: Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), 20.0),
+            scale: Vec3::new(2.0, 2.0, 2.0),
+        };
+        scene.add_node("back", transform, Some(back), None);
+        light_from(&mut scene, &Vec3::new(-0.25, 0.5, -0.75));
+
+        let screen_size = ScreenSize { width: 101, height: 77 };
+        let mut single = DepthBuffer::new(screen_size);
+        let single_stats = render(&mut single, &scene, 1);
+        for thread_count in [2, 3, 8] {
+            let mut tiled = DepthBuffer::new(screen_size);
+            assert_eq!(render(&mut tiled, &scene, thread_count), single_stats);
+            for (a, b) in single.buffer.iter().zip(&tiled.buffer) {
+                assert_eq!(a.depth.to_bits(), b.depth.to_bits());
+                assert_eq!((a.color.red, a.color.green, a.color.blue), (b.color.red, b.color.green, b.color.blue));
+            }
+        }
+        // both cubes and the background are visible
+        let reds: HashSet<u8> = single.buffer.iter().map(|pixel| pixel.color.red).collect();
+        assert!(reds.len() > 10);
+    }
+
+    /// Turns the sun of the default scene so that its light comes from `direction`.
+    fn light_from(scene: &mut Scene, direction: &Vec3) {
+        let sun = scene.find("sun").unwrap();
+        let rotation = Quat::look_rotation(&-direction, &Vec3::new(0.0, 1.0, 0.0));
+        scene.node_mut(sun).unwrap().transform.rotation = rotation;
+    }
+
+    /// Unit sphere with exact normals and counter-clockwise triangles seen from outside.
+    fn sphere(rings: usize, segments: usize) -> Mesh {
+        let mut vertices = vec![];
+        for ring in 0..=rings {
+            let latitude = (ring as f32 / rings as f32 - 0.5) * std::f32::consts::PI;
+            for segment in 0..=segments {
+                let longitude = segment as f32 / segments as f32 * 2.0 * std::f32::consts::PI;
+                let (x, y, z) = (latitude.cos() * longitude.cos(), latitude.sin(), latitude.cos() * longitude.sin());
+                let mut vertex = Vertex::new(Vec4::new3d(x, y, z));
+                vertex.normal = Some(Vec4::new(x, y, z, 0.0));
+                vertices.push(vertex);
+            }
+        }
+        let mut indices = vec![];
+        let index = |ring: usize, segment: usize| (ring * (segments + 1) + segment) as u32;
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let (a, b) = (index(ring, segment), index(ring, segment + 1));
+                let (c, d) = (index(ring + 1, segment + 1), index(ring + 1, segment));
+                indices.extend([a, c, b, a, d, c]);
+            }
+        }
+        return Mesh::new(vertices, indices);
+    }
+
+    #[test]
+    fn shading_mode_changes_the_output() {
+        // a coarse sphere, where flat faces, per-vertex lighting and per-pixel highlights all differ
+        let render_with = |shading: Shading| {
+            let mut scene = Scene::new(sphere(8, 16), vec![Material::default()]);
+            light_from(&mut scene, &Vec3::new(-0.3, 0.6, -0.55));
+            let object = scene.object_mut(0).unwrap();
+            object.shading = shading;
+            object.wireframe = false;
+            scene.node_mut(0).unwrap().transform = Transform::from_translation(Vec3::new(0.0, 0.0, 1.5));
+            let mut buffer = DepthBuffer::new(ScreenSize { width: 64, height: 48 });
+            render(&mut buffer, &scene, 1);
+            return buffer.buffer.iter().map(|pixel| (pixel.color.red, pixel.color.green, pixel.color.blue)).collect::<Vec<_>>();
+        };
+        let images = [Shading::Flat, Shading::Gouraud, Shading::Phong].map(render_with);
+        for (first, second) in [(0, 1), (0, 2), (1, 2)] {
+            let differing = images[first].iter().zip(&images[second]).filter(|(a, b)| a != b).count();
+            assert!(differing > 100, "shadings {} and {} differ in {} pixels", first, second, differing);
+        }
+    }
+
+    #[test]
+    fn scaled_sphere_is_lit_like_an_ellipsoid() {
+        let screen_size = ScreenSize { width: 120, height: 90 };
+        for scale in [Vec3::new(0.9, 0.35, 0.5), Vec3::new(-0.9, 0.35, 0.5)] {
+            let mut scene = Scene::new(sphere(64, 128), vec![Material::default()]);
+            light_from(&mut scene, &Vec3::new(-0.3, 0.6, -0.55));
+            scene.object_mut(0).unwrap().wireframe = false;
+            let model_mat = {
+                let node = scene.node_mut(0).unwrap();
+                node.transform = Transform {
+                    translation: Vec3::new(0.0, 0.0, 1.2),
+                    rotation: Quat::from_euler(&Vec3::new(30.0, -20.0, 45.0)),
+                    scale,
+                };
+                node.transform.matrix()
+            };
+            let mut buffer = DepthBuffer::new(screen_size);
+            render(&mut buffer, &scene, 1);
+
+            // cast a ray through every pixel center at the analytic ellipsoid, which is the unit sphere
+            // in object space, and light the hit point with the exact ellipsoid normal
+            let eye = Vec4::new3d(0.0, 0.0, -1.0);
+            let aspect_ratio = screen_size.width as f32 / screen_size.height as f32;
+            let lighting = SceneLighting::new(&scene, &scene.world_matrices(), aspect_ratio, None);
+            let to_object = model_mat.inverse().unwrap();
+            let (normal_mat, material) = (model_mat.normal_matrix(), Material::default());
+            let tan_half_fov = (scene.camera.vertical_fov.to_radians() / 2.0).tan();
+            let (mut compared, mut mismatched, mut naive_mismatched) = (0, 0, 0);
+            for y in 0..screen_size.height {
+                for x in 0..screen_size.width {
+                    let ndc_x = (x as f32 + 0.5) / screen_size.width as f32 * 2.0 - 1.0;
+                    let ndc_y = (y as f32 + 0.5) / screen_size.height as f32 * 2.0 - 1.0;
+                    let direction = Vec4::new(ndc_x * tan_half_fov * aspect_ratio, ndc_y * tan_half_fov, 1.0, 0.0);
+                    let (origin, direction) = (&to_object * &eye, &to_object * &direction);
+                    // |origin + t * direction| = 1
+                    let a = direction.dot(&direction);
+                    let b = 2.0 * origin.dot(&direction);
+                    let c = origin.dot(&origin) - 1.0;
+                    let discriminant = b * b - 4.0 * a * c;
+                    if discriminant < 0.0 {
+                        continue;
+                    }
+                    let t = (-b - discriminant.sqrt()) / (2.0 * a);
+                    let local = &origin + &(&direction * t);
+                    let local = Vec3::new(local.x, local.y, local.z);
+                    let world_position = &model_mat * &Vec4::new3d(local.x, local.y, local.z);
+                    let normal = (&normal_mat * &local).normalized();
+                    let normal = Vec4::new(normal.x, normal.y, normal.z, 0.0);
+                    // the tessellated silhouette differs from the analytic one
+                    if normal.dot(&(&eye - &world_position).normalized()) < 0.2 {
+                        continue;
+                    }
+                    let rendered = buffer.buffer[buffer.index(x, y).unwrap()].color;
+                    let differs = |expected: Color| {
+                        [(rendered.red, expected.red), (rendered.green, expected.green), (rendered.blue, expected.blue)]
+                            .iter().any(|&(a, b)| a.abs_diff(b) > 4)
+                    };
+                    compared += 1;
+                    mismatched += differs(to_color(&lighting.shade(&material, &world_position, &normal, &WHITE))) as usize;
+
+                    // normals transformed like positions lean towards the stretched axes
+                    let naive = &model_mat * &Vec4::new(local.x, local.y, local.z, 0.0);
+                    let mut naive = &naive.normalized() * scale.x.signum();
+                    naive.w = 0.0;
+                    naive_mismatched += differs(to_color(&lighting.shade(&material, &world_position, &naive, &WHITE))) as usize;
+                }
+            }
+            assert!(compared > 1000, "{}", compared);
+            assert!(mismatched * 100 < compared, "{} of {} pixels differ", mismatched, compared);
+            assert!(naive_mismatched * 3 > compared, "{} of {} pixels differ", naive_mismatched, compared);
+        }
+    }
+
+    /// Unit cube resting on the center of a square floor at y = 0 with sides `floor_size` long, both
+    /// without wireframe or specular highlights, and the sun shining along (1, -1, 0).
+    fn cube_on_floor(floor_size: f32) -> Scene {
+        let material = Material {
+            ambient: Vec3::new(0.1, 0.1, 0.1),
+            diffuse: Vec3::new(0.8, 0.8, 0.8),
+            illumination: 1,
+            ..Material::default()
+        };
+        let mut scene = Scene::new(cube(), vec![material.clone()]);
+        let box_object = scene.object_mut(0).unwrap();
+        box_object.wireframe = false;
+        box_object.mesh.vertices.iter_mut().for_each(|v| v.position.z -= 2.0);
+        scene.node_mut(0).unwrap().transform = Transform::from_translation(Vec3::new(0.0, 0.5, 0.0));
+        let half = floor_size / 2.0;
+        let floor_vertices = [(-half, -half), (-half, half), (half, half), (half, -half)].map(|(x, z)| {
+            let mut vertex = Vertex::new(Vec4::new3d(x, 0.0, z));
+            vertex.normal = Some(Vec4::new(0.0, 1.0, 0.0, 0.0));
+            vertex
+        });
+        let mut floor = GameObject::new(Mesh::new(floor_vertices.to_vec(), vec![0, 1, 2, 0, 2, 3]), vec![material]);
+        floor.wireframe = false;
+        scene.add_node("floor", Transform::default(), Some(floor), None);
+        light_from(&mut scene, &Vec3::new(-1.0, 1.0, 0.0));
+        return scene;
+    }
+
+    #[test]
+    fn cube_casts_a_shadow_on_the_floor() {
+        // the shadow covers x in -0.5..1.5 and z in -0.5..0.5 and is seen from above and in front
+        let mut scene = cube_on_floor(6.0);
+        let eye = Vec3::new(0.0, 4.0, -4.0);
+        scene.node_mut(scene.camera_node).unwrap().transform = Transform {
+            translation: eye,
+            rotation: Quat::look_rotation(&-&eye, &Vec3::new(0.0, 1.0, 0.0)),
+            ..Transform::default()
+        };
+        scene.camera.z_far = 20.0;
+        let sun = scene.find("sun").unwrap();
+
+        let screen_size = ScreenSize { width: 160, height: 120 };
+        let clip_mat = &scene.camera.perspective_mat(screen_size.width as f32 / screen_size.height as f32) *
+            &Camera::view_mat(&scene.world_matrices()[scene.camera_node]);
+        let floor_red = |scene: &Scene, points: &[(f32, f32)]| {
+            let mut buffer = DepthBuffer::new(screen_size);
+            render(&mut buffer, scene, 1);
+            return points.iter().map(|&(x, z)| {
+                let mut projected = &clip_mat * &Vec4::new3d(x, 0.0, z);
+                projected.perspective_div();
+                let pixel_x = ((projected.x + 1.0) / 2.0 * screen_size.width as f32) as i32;
+                let pixel_y = ((projected.y + 1.0) / 2.0 * screen_size.height as f32) as i32;
+                buffer.buffer[buffer.index(pixel_x, pixel_y).unwrap()].color.red
+            }).collect::<Vec<u8>>();
+        };
+        let lit = (((0.1 + 0.8 * 0.5_f32.sqrt()) * 255.0).round()) as u8;
+        let dark = (0.1_f32 * 255.0).round() as u8;
+        let shadowed_points = [(1.0, 0.0), (1.2, -0.3), (0.7, 0.3), (1.4, 0.4)];
+        let lit_points = [(-1.5, 0.0), (1.0, 1.0), (2.0, 0.0), (0.5, -0.8)];
+
+        assert!(floor_red(&scene, &shadowed_points).iter().all(|&red| red == lit));
+        let shadow = Shadow { resolution: 256, bias: 0.05, pcf_radius: 1, cascades: 1, debug_cascades: false };
+        scene.node_mut(sun).unwrap().shadow = Some(shadow);
+        assert!(floor_red(&scene, &shadowed_points).iter().all(|&red| red == dark));
+        assert!(floor_red(&scene, &lit_points).iter().all(|&red| red == lit));
+
+        // crossing the far edge of the shadow, filtering blends between the two levels
+        let edge: Vec<(f32, f32)> = (0..80).map(|i| (1.3 + i as f32 * 0.005, 0.0)).collect();
+        let levels = |scene: &Scene| floor_red(scene, &edge).into_iter().collect::<HashSet<u8>>();
+        scene.node_mut(sun).unwrap().shadow = Some(Shadow { pcf_radius: 0, ..shadow });
+        assert_eq!(levels(&scene), HashSet::from([dark, lit]));
+        scene.node_mut(sun).unwrap().shadow = Some(Shadow { bias: 0.12, pcf_radius: 3, ..shadow });
+        assert!(levels(&scene).len() > 3);
+    }
+
+    #[test]
+    fn cascades_keep_near_shadows_sharp_over_long_views() {
+        // a ten times larger cube far away, whose shadow covers x in -5..15 and z in 75..85
+        let mut scene = cube_on_floor(400.0);
+        let mut big_cube = GameObject::new(cube(), vec![Material::default()]);
+        big_cube.mesh.vertices.iter_mut().for_each(|v| v.position.z -= 2.0);
+        let transform = Transform {
+            translation: Vec3::new(0.0, 5.0, 80.0),
+            scale: Vec3::new(10.0, 10.0, 10.0),
+            ..Transform::default()
+        };
+        scene.add_node("big cube", transform, Some(big_cube), None);
+        scene.node_mut(scene.camera_node).unwrap().transform = Transform {
+            translation: Vec3::new(0.0, 3.0, -6.0),
+            rotation: Quat::from_axis_angle(&Vec3::new(1.0, 0.0, 0.0), 15.0),
+            ..Transform::default()
+        };
+        scene.camera.z_far = 150.0;
+        let sun = scene.find("sun").unwrap();
+        let shadow = Shadow { resolution: 512, bias: 0.08, pcf_radius: 1, cascades: 4, debug_cascades: false };
+        let floor_brightness = |scene: &Scene, points: &[(f32, f32)]| {
+            let lighting = SceneLighting::new(scene, &scene.world_matrices(), 4.0 / 3.0, None);
+            let material = &scene.node(scene.find("floor").unwrap()).unwrap().object.as_ref().unwrap().materials[0];
+            return points.iter().map(|&(x, z)| {
+                let normal = Vec4::new(0.0, 1.0, 0.0, 0.0);
+                lighting.shade(material, &Vec4::new3d(x, 0.0, z), &normal, &WHITE)
+            }).collect::<Vec<Vec3>>();
+        };
+        let (dark, lit) = (0.1, 0.1 + 0.8 * 0.5_f32.sqrt());
+        // close to both sides of the near shadow's edge, and in and next to the far shadow
+        let points = [(1.3, 0.0), (1.7, 0.0), (8.0, 80.0), (-8.0, 80.0), (8.0, 70.0)];
+        let expected = [dark, lit, dark, lit, lit];
+
+        scene.node_mut(sun).unwrap().shadow = Some(shadow);
+        let cascaded: Vec<f32> = floor_brightness(&scene, &points).iter().map(|color| color.x).collect();
+        for (actual, expected) in cascaded.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-4, "{:?} is not {:?}", cascaded, expected);
+        }
+        // one map of the same resolution over the whole floor blurs the near edge
+        scene.node_mut(sun).unwrap().shadow = Some(Shadow { cascades: 1, ..shadow });
+        let single = floor_brightness(&scene, &points[..2]);
+        assert!(single.iter().any(|color| (color.x - dark).abs() > 1e-4 && (color.x - lit).abs() > 1e-4));
+
+        // the tint shows which cascade a point uses, red for the first one and yellow for the fourth
+        scene.node_mut(sun).unwrap().shadow = Some(Shadow { debug_cascades: true, ..shadow });
+        let [near, far] = floor_brightness(&scene, &[(-2.0, 0.0), (-20.0, 120.0)]).try_into().unwrap();
+        assert!(near.x > near.y && (near.y - near.z).abs() < 1e-6, "{:?}", near);
+        assert!((far.x - far.y).abs() < 1e-6 && far.z < far.x, "{:?}", far);
+    }
+
+    #[test]
+    fn shadow_maps_are_drawn_over_in_the_next_frame() {
+        let mut scene = cube_on_floor(6.0);
+        let eye = Vec3::new(0.0, 4.0, -4.0);
+        scene.node_mut(scene.camera_node).unwrap().transform = Transform {
+            translation: eye,
+            rotation: Quat::look_rotation(&-&eye, &Vec3::new(0.0, 1.0, 0.0)),
+            ..Transform::default()
+        };
+        scene.camera.z_far = 20.0;
+        let sun = scene.find("sun").unwrap();
+        let shadow = Shadow { resolution: 128, bias: 0.03, pcf_radius: 1, cascades: 2, debug_cascades: false };
+        scene.node_mut(sun).unwrap().shadow = Some(shadow);
+        let depths = |lighting: &Option<SceneLighting>| {
+            let shadow_map = lighting.as_ref().unwrap().lights[0].shadow_map.as_ref().unwrap();
+            return shadow_map.cascades.iter()
+                .map(|cascade| (cascade.depth.buffer.as_ptr(), cascade.depth.screen_size.width))
+                .collect::<HashSet<_>>();
+        };
+
+        // the second frame, with the light turned, keeps the buffers but none of the old depths
+        let screen_size = ScreenSize { width: 64, height: 48 };
+        let mut reused = DepthBuffer::new(screen_size);
+        let mut lighting = None;
+        render_reusing_shadow_maps(&mut reused, &scene, 1, &mut lighting);
+        let first_depths = depths(&lighting);
+        light_from(&mut scene, &Vec3::new(1.0, 1.0, 0.5));
+        render_reusing_shadow_maps(&mut reused, &scene, 1, &mut lighting);
+        assert_eq!(depths(&lighting), first_depths);
+        let fresh = SceneLighting::new(&scene, &scene.world_matrices(), 64.0 / 48.0, None);
+        let cascades = |lighting: &SceneLighting| lighting.lights[0].shadow_map.as_ref().unwrap().cascades.iter()
+            .flat_map(|cascade| cascade.depth.buffer.iter().map(|texel| texel.depth.to_bits()))
+            .collect::<Vec<u32>>();
+        assert_eq!(cascades(lighting.as_ref().unwrap()), cascades(&fresh));
+
+        // maps of another resolution get new buffers
+        scene.node_mut(sun).unwrap().shadow = Some(Shadow { resolution: 64, ..shadow });
+        render_reusing_shadow_maps(&mut reused, &scene, 1, &mut lighting);
+        assert!(depths(&lighting).iter().all(|&(_, width)| width == 64));
+    }
+
+    #[test]
+    fn cascades_snap_to_whole_texels_as_the_camera_moves() {
+        let mut scene = cube_on_floor(400.0);
+        scene.camera.z_far = 150.0;
+        let sun = scene.find("sun").unwrap();
+        let shadow = Shadow { resolution: 256, bias: 0.03, pcf_radius: 1, cascades: 3, debug_cascades: false };
+        scene.node_mut(sun).unwrap().shadow = Some(shadow);
+        let texel_offsets = |scene: &Scene| {
+            let lighting = SceneLighting::new(scene, &scene.world_matrices(), 4.0 / 3.0, None);
+            let shadow_map = lighting.lights[0].shadow_map.as_ref().unwrap();
+            return shadow_map.cascades.iter().map(|cascade| {
+                let projected = &cascade.clip_mat * &Vec4::new3d(0.0, 0.0, 0.0);
+                let texel = |ndc: f32| (ndc + 1.0) / 2.0 * shadow.resolution as f32;
+                (cascade.clip_mat.content[0][0], texel(projected.x), texel(projected.y))
+            }).collect::<Vec<_>>();
+        };
+        let before = texel_offsets(&scene);
+        let camera = scene.node_mut(scene.camera_node).unwrap();
+        camera.transform.translation = &camera.transform.translation + &Vec3::new(0.37, 0.11, 1.93);
+        camera.transform.rotate(&Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), 17.0));
+        let after = texel_offsets(&scene);
+        // the maps keep their size, and a fixed point moves across them by whole texels
+        for ((scale_before, x_before, y_before), (scale_after, x_after, y_after)) in before.into_iter().zip(after) {
+            assert_eq!(scale_before, scale_after);
+            for offset in [x_after - x_before, y_after - y_before] {
+                assert!((offset - offset.round()).abs() < 1e-2, "{}", offset);
+            }
+        }
+    }
+
+    #[test]
+    fn clipping_keeps_the_part_inside_the_view_volume() {
+        // a triangle sticking out on the left and right becomes a pentagon, with uv equal to the position
+        let vertex = |x: f32, y: f32| ClipVertex {
+            position: Vec4::new(x, y, 0.5, 1.0),
+            varyings: Varyings { uv: Vec2::new(x, y), ..Varyings::default() },
+        };
+        let mut polygon = vec![vertex(-2.0, 0.0), vertex(2.0, 0.0), vertex(0.0, 0.5)];
+        clip_polygon(&mut polygon, &mut vec![]);
+        let corners: Vec<(f32, f32)> = polygon.iter().map(|v| (v.position.x, v.position.y)).collect();
+        assert_eq!(corners, vec![(-1.0, 0.0), (1.0, 0.0), (1.0, 0.25), (0.0, 0.5), (-1.0, 0.25)]);
+        for v in &polygon {
+            assert_eq!((v.varyings.uv.x, v.varyings.uv.y), (v.position.x, v.position.y));
+        }
+
+        // behind the camera and beyond the far plane nothing is left
+        let mut behind = vec![vertex(0.0, 0.0), vertex(0.5, 0.0), vertex(0.0, 0.5)];
+        behind.iter_mut().for_each(|v| v.position.z = -0.1);
+        clip_polygon(&mut behind, &mut vec![]);
+        assert!(behind.is_empty());
+        let mut far = vec![vertex(0.0, 0.0), vertex(0.5, 0.0), vertex(0.0, 0.5)];
+        far.iter_mut().for_each(|v| v.position.z = 1.1);
+        clip_polygon(&mut far, &mut vec![]);
+        assert!(far.is_empty());
+    }
+
+    #[test]
+    fn tessellated_quad_covers_every_pixel_exactly_once() {
+        // a fan of triangles around an off-center point inside a rotated quad, with vertices on
+        // pixel centers, edges through pixel centers, and edges at arbitrary sub-pixel positions
+        let screen_size = ScreenSize { width: 40, height: 30 };
+        let to_ndc = |x: f32, y: f32| Vec4::new(x / 20.0 - 1.0, y / 15.0 - 1.0, 0.5, 1.0);
+        let quad = [(3.5, 2.5), (36.2, 6.5), (31.5, 27.5), (5.0, 20.25)];
+        let center = (17.5, 13.5);
+        let mut triangles = vec![];
+        for i in 0..4 {
+            let (a, b) = (quad[i], quad[(i + 1) % 4]);
+            let middle = ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+            triangles.push([center, a, middle]);
+            // alternate the winding, both have to be filled alike
+            triangles.push([center, b, middle]);
+        }
+
+        let mut counts = vec![0; (screen_size.width * screen_size.height) as usize];
+        for corners in triangles {
+            let [p1, p2, p3] = corners.map(|(x, y)| to_ndc(x, y));
+            let tr = Triangle::new(p1, p2, p3);
+            rasterize_triangle(screen_size, screen_size.rect(), &tr, &[Varyings::default(), Varyings::default(), Varyings::default()],
+                               |x, y, _, _| counts[(y * screen_size.width + x) as usize] += 1);
+        }
+
+        // reference: a pixel belongs to the quad if its center is inside, or exactly on a top or left
+        // edge, which only happens for the horizontal and vertical free edges here
+        let inside = |px: f32, py: f32| (0..4).all(|i| {
+            let (a, b) = (quad[i], quad[(i + 1) % 4]);
+            (b.0 - a.0) * (py - a.1) - (b.1 - a.1) * (px - a.0) > 0.0
+        });
+        let mut covered = 0;
+        for y in 0..screen_size.height {
+            for x in 0..screen_size.width {
+                let count = counts[(y * screen_size.width + x) as usize];
+                assert!(count <= 1, "pixel ({}, {}) written {} times", x, y, count);
+                if inside(x as f32 + 0.5, y as f32 + 0.5) {
+                    assert_eq!(count, 1, "pixel ({}, {}) inside the quad was not written", x, y);
+                }
+                covered += count;
+            }
+        }
+        assert!(covered > 500);
+    }
+
+    #[test]
+    fn thin_triangles_do_not_divide_by_zero() {
+        // one pixel wide and sub-pixel sized triangles only cover the pixel centers inside them
+        let screen_size = ScreenSize { width: 10, height: 10 };
+        let to_ndc = |x: f32, y: f32| Vec4::new(x / 5.0 - 1.0, y / 5.0 - 1.0, 0.5, 1.0);
+        let mut written = vec![];
+        let thin = Triangle::new(to_ndc(4.0, 1.0), to_ndc(5.0, 1.0), to_ndc(4.0, 9.0));
+        let varyings = [Varyings::default(), Varyings::default(), Varyings::default()];
+        rasterize_triangle(screen_size, screen_size.rect(), &thin, &varyings, |x, y, _, _| written.push((x, y)));
+        assert!(written.iter().all(|&(x, _)| x == 4));
+        assert_eq!(written.len(), 4);
+
+        let tiny = Triangle::new(to_ndc(4.1, 4.1), to_ndc(4.3, 4.1), to_ndc(4.1, 4.3));
+        rasterize_triangle(screen_size, screen_size.rect(), &tiny, &varyings, |x, y, _, _| panic!("({}, {}) written", x, y));
+    }
+}
@@ -0,0 +1,221 @@
+use crate::math::{Lerp, Vec2, Vec3};
+use crate::Color;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    /// Bilinear lookups in the two mip levels closest to the screen-space footprint, blended together.
+    Trilinear,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+struct MipLevel {
+    width: usize,
+    height: usize,
+    /// sRGB channels scaled to 0..1 without converting them to linear light, rows from top to bottom.
+    texels: Vec<Vec3>,
+}
+
+/// Image sampled with uv coordinates, where (0, 0) is the bottom left corner as in OBJ files.
+pub struct Texture {
+    /// Level 0 is the full image, every next one is half the size down to 1x1.
+    levels: Vec<MipLevel>,
+    pub filter: Filter,
+    pub wrap: Wrap,
+}
+
+impl std::fmt::Debug for Texture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (width, height) = self.size();
+        write!(f, "Texture({}x{}, {:?}, {:?})", width, height, self.filter, self.wrap)
+    }
+}
+
+impl MipLevel {
+    fn texel(&self, x: i64, y: i64, wrap: Wrap) -> &Vec3 {
+        let x = wrap_coordinate(x, self.width, wrap);
+        let y = wrap_coordinate(y, self.height, wrap);
+        return &self.texels[y * self.width + x];
+    }
+
+    fn sample_nearest(&self, uv: &Vec2<f32>, wrap: Wrap) -> Vec3 {
+        let x = (uv.x * self.width as f32).floor() as i64;
+        let y = ((1.0 - uv.y) * self.height as f32).floor() as i64;
+        return *self.texel(x, y, wrap);
+    }
+
+    fn sample_bilinear(&self, uv: &Vec2<f32>, wrap: Wrap) -> Vec3 {
+        // texel centers sit at half-integer coordinates
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = (1.0 - uv.y) * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        // huge or infinite coordinates saturate at the ends of the i64 range
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let (x1, y1) = (x0.saturating_add(1), y0.saturating_add(1));
+
+        let top = self.texel(x0, y0, wrap).lerp(self.texel(x1, y0, wrap), fx);
+        let bot = self.texel(x0, y1, wrap).lerp(self.texel(x1, y1, wrap), fx);
+        return top.lerp(&bot, fy);
+    }
+
+    /// Halves the size, averaging the block of texels behind every new texel. With an odd size
+    /// the last block is three texels wide instead of two, so no texel is skipped.
+    fn downsampled(&self) -> MipLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut texels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let source_rows = y * self.height / height..(y + 1) * self.height / height;
+            for x in 0..width {
+                let source_columns = x * self.width / width..(x + 1) * self.width / width;
+                let mut sum = Vec3::default();
+                for source_y in source_rows.clone() {
+                    for source_x in source_columns.clone() {
+                        sum = &sum + &self.texels[source_y * self.width + source_x];
+                    }
+                }
+                texels.push(&sum * (1.0 / (source_rows.len() * source_columns.len()) as f32));
+            }
+        }
+        return MipLevel { width, height, texels };
+    }
+}
+
+impl Texture {
+    /// Builds a texture with a full mip chain from `width * height` pixels stored row by row from the top.
+    pub fn new(width: usize, height: usize, pixels: &[Color], filter: Filter, wrap: Wrap) -> Self {
+        let normalize = |channel: u8| channel as f32 / 255.0;
+        let texels = pixels.iter()
+            .map(|color| Vec3::new(normalize(color.red), normalize(color.green), normalize(color.blue)))
+            .collect();
+        let mut levels = vec![MipLevel { width, height, texels }];
+        while let Some(level) = levels.last().filter(|level| level.width > 1 || level.height > 1) {
+            levels.push(level.downsampled());
+        }
+        return Self { levels, filter, wrap };
+    }
+
+    /// Width and height of the full resolution image.
+    pub fn size(&self) -> (usize, usize) {
+        (self.levels[0].width, self.levels[0].height)
+    }
+
+    /// Samples the texture at `uv`, where `uv_dx` and `uv_dy` are the uv changes between neighbouring
+    /// pixels on screen and select the mip level for trilinear filtering.
+    pub fn sample(&self, uv: &Vec2<f32>, uv_dx: &Vec2<f32>, uv_dy: &Vec2<f32>) -> Vec3 {
+        match self.filter {
+            Filter::Nearest => self.levels[0].sample_nearest(uv, self.wrap),
+            Filter::Bilinear => self.levels[0].sample_bilinear(uv, self.wrap),
+            Filter::Trilinear => {
+                let lod = self.level_of_detail(uv_dx, uv_dy);
+                let level = lod.floor() as usize;
+                if level + 1 >= self.levels.len() {
+                    return self.levels[self.levels.len() - 1].sample_bilinear(uv, self.wrap);
+                }
+                let finer = self.levels[level].sample_bilinear(uv, self.wrap);
+                let coarser = self.levels[level + 1].sample_bilinear(uv, self.wrap);
+                return finer.lerp(&coarser, lod.fract());
+            }
+        }
+    }
+
+    /// Mip level whose texels are about one pixel in size, from the longer side of the pixel footprint.
+    /// Footprints too large to measure read the last level.
+    fn level_of_detail(&self, uv_dx: &Vec2<f32>, uv_dy: &Vec2<f32>) -> f32 {
+        let (width, height) = (self.levels[0].width as f32, self.levels[0].height as f32);
+        let footprint_x = (uv_dx.x * width).hypot(uv_dx.y * height);
+        let footprint_y = (uv_dy.x * width).hypot(uv_dy.y * height);
+        let lod = footprint_x.max(footprint_y).log2();
+        let coarsest = (self.levels.len() - 1) as f32;
+        return if lod.is_finite() { lod.clamp(0.0, coarsest) } else { coarsest };
+    }
+}
+
+fn wrap_coordinate(coordinate: i64, size: usize, wrap: Wrap) -> usize {
+    let size = size as i64;
+    let wrapped = match wrap {
+        Wrap::Repeat => coordinate.rem_euclid(size),
+        Wrap::Clamp => coordinate.clamp(0, size - 1),
+        Wrap::Mirror => {
+            let period = coordinate.rem_euclid(2 * size);
+            if period < size { period } else { 2 * size - 1 - period }
+        }
+    };
+    return wrapped as usize;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(value: u8) -> Color {
+        Color { red: value, green: value, blue: value, alpha: 0 }
+    }
+
+    #[test]
+    fn wrap_modes() {
+        assert_eq!([-1, 0, 3, 4, 5].map(|x| wrap_coordinate(x, 4, Wrap::Repeat)), [3, 0, 3, 0, 1]);
+        assert_eq!([-1, 0, 3, 4, 5].map(|x| wrap_coordinate(x, 4, Wrap::Clamp)), [0, 0, 3, 3, 3]);
+        assert_eq!([-1, 0, 3, 4, 5].map(|x| wrap_coordinate(x, 4, Wrap::Mirror)), [0, 0, 3, 3, 2]);
+    }
+
+    #[test]
+    fn bilinear_blends_neighbouring_texels() {
+        let texture = Texture::new(2, 1, &[gray(0), gray(255)], Filter::Bilinear, Wrap::Clamp);
+        let zero = Vec2::new(0.0, 0.0);
+        let sample = |u: f32| texture.sample(&Vec2::new(u, 0.5), &zero, &zero).x;
+        assert_eq!(sample(0.25), 0.0);
+        assert!((sample(0.5) - 0.5).abs() < 1e-6);
+        assert_eq!(sample(0.75), 1.0);
+    }
+
+    #[test]
+    fn mip_chain_averages_down_to_one_texel() {
+        let pixels: Vec<Color> = (0..5 * 3).map(|i| gray(if i % 2 == 0 { 255 } else { 0 })).collect();
+        let texture = Texture::new(5, 3, &pixels, Filter::Trilinear, Wrap::Repeat);
+        let sizes: Vec<(usize, usize)> = texture.levels.iter().map(|level| (level.width, level.height)).collect();
+        assert_eq!(sizes, vec![(5, 3), (2, 1), (1, 1)]);
+
+        // a footprint covering the whole texture reads the last level only
+        let uv = Vec2::new(0.3, 0.3);
+        let whole = Vec2::new(1.0, 0.0);
+        let last = &texture.levels[2].texels[0];
+        assert_eq!(texture.sample(&uv, &whole, &whole).x, last.x);
+        assert!(last.x > 0.0 && last.x < 1.0);
+
+        // one texel per pixel reads level 0
+        let texel = Vec2::new(1.0 / 5.0, 0.0);
+        assert_eq!(texture.level_of_detail(&texel, &texel), 0.0);
+        let two_texels = Vec2::new(2.0 / 5.0, 0.0);
+        assert!((texture.level_of_detail(&two_texels, &two_texels) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn infinite_uvs_and_footprints_do_not_panic() {
+        let texture = Texture::new(2, 2, &[gray(0), gray(255), gray(255), gray(0)], Filter::Trilinear, Wrap::Mirror);
+        let zero = Vec2::new(0.0, 0.0);
+        let infinite = Vec2::new(f32::INFINITY, f32::NEG_INFINITY);
+        assert_eq!(texture.level_of_detail(&infinite, &zero), 1.0);
+        assert_eq!(texture.level_of_detail(&Vec2::new(f32::NAN, 0.0), &zero), 1.0);
+        assert_eq!(texture.level_of_detail(&Vec2::new(1e30, 0.0), &zero), 1.0);
+        let uv = Vec2::new(0.25, 0.25);
+        assert_eq!(texture.sample(&uv, &infinite, &infinite).x, texture.levels[1].texels[0].x);
+        for filter in [Filter::Nearest, Filter::Bilinear, Filter::Trilinear] {
+            for wrap in [Wrap::Repeat, Wrap::Clamp, Wrap::Mirror] {
+                let texture = Texture::new(2, 2, &[gray(0); 4], filter, wrap);
+                texture.sample(&infinite, &zero, &zero);
+                texture.sample(&Vec2::new(3e38, f32::INFINITY), &infinite, &infinite);
+            }
+        }
+    }
+}
@@ -11,8 +11,11 @@
 @interface GameView : NSView
 
 @property NSTimer *gameLoopTimer;
+@property EngineHandle engine;
 @property uint64_t prevTimeNs;
 @property Color* bitmap;
+@property int32_t bitmapWidth;
+@property int32_t bitmapHeight;
 
 - (void)startLoop;
 
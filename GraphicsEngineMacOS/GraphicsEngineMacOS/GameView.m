@@ -12,8 +12,14 @@
 UserInput input;
 
 - (void)startLoop {
-    create_scene();
+    self.engine = engine_create();
+    const char* error = engine_last_error(self.engine);
+    if (error != NULL) {
+        NSLog(@"Failed to load the model: %s", error);
+    }
     self.bitmap = NULL;
+    self.bitmapWidth = 0;
+    self.bitmapHeight = 0;
     self.prevTimeNs = clock_gettime_nsec_np(CLOCK_MONOTONIC_RAW);
     self.gameLoopTimer = [NSTimer scheduledTimerWithTimeInterval:0
                                                           target:self
@@ -22,6 +28,11 @@ UserInput input;
                                                          repeats:YES];
 }
 
+- (void)dealloc {
+    engine_destroy(self.engine);
+    free(self.bitmap);
+}
+
 - (void)triggerDraw {
     [self setNeedsDisplay:YES];
 }
@@ -37,8 +48,15 @@ UserInput input;
     int32_t width = self.bounds.size.width;
     int32_t height = self.bounds.size.height;
     
-    free_bitmap(self.bitmap, width * height);
-    self.bitmap = update_and_render(width, height, input, deltaTime);
+    if (width != self.bitmapWidth || height != self.bitmapHeight) {
+        free(self.bitmap);
+        self.bitmap = malloc(width * height * sizeof(Color));
+        self.bitmapWidth = width;
+        self.bitmapHeight = height;
+    }
+    if (!engine_update_and_render(self.engine, input, deltaTime, self.bitmap, width, height, width)) {
+        return;
+    }
     
     CGContextRef gContext = [[NSGraphicsContext currentContext] CGContext];
     